@@ -0,0 +1,137 @@
+use actix_http::header;
+use actix_web::HttpRequest;
+
+use crate::img::ImageFormat;
+
+/// Pick the best image format for a request based on its `Accept` header
+///
+/// Only formats explicitly listed in the `Accept` header are considered (a wildcard
+/// like `image/*` does not guarantee support for newer formats). The format with the
+/// highest q-value wins and ties are broken by the order of the `preferences`.
+pub fn preferred_format(request: &HttpRequest, preferences: &[ImageFormat]) -> Option<ImageFormat> {
+    let accept = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())?;
+
+    negotiate(accept, preferences)
+}
+
+fn negotiate(accept: &str, preferences: &[ImageFormat]) -> Option<ImageFormat> {
+    let ranges = accept
+        .split(',')
+        .filter_map(media_range)
+        .collect::<Vec<_>>();
+
+    preferences
+        .iter()
+        .filter_map(|format| {
+            ranges
+                .iter()
+                .find(|(media_type, _)| media_type.eq_ignore_ascii_case(format.mime_type()))
+                .map(|(_, quality)| (*format, *quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        // keep the first (most preferred) format when q-values are equal
+        .fold(
+            None,
+            |best: Option<(ImageFormat, f32)>, candidate| match best {
+                Some(best) if best.1 >= candidate.1 => Some(best),
+                _ => Some(candidate),
+            },
+        )
+        .map(|(format, _)| format)
+}
+
+/// Check if a request prefers JSON over plain text (e.g. `Accept: application/json`)
+pub fn prefers_json(request: &HttpRequest) -> bool {
+    request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map(wants_json)
+        .unwrap_or(false)
+}
+
+fn wants_json(accept: &str) -> bool {
+    let quality = |matches: fn(&str) -> bool| {
+        accept
+            .split(',')
+            .filter_map(media_range)
+            .filter(|(media_type, _)| matches(&media_type.to_ascii_lowercase()))
+            .map(|(_, quality)| quality)
+            .fold(0.0, f32::max)
+    };
+
+    let json =
+        quality(|media_type| media_type == "application/json" || media_type.ends_with("+json"));
+    // a bare `*/*` (sent by most clients) keeps the plain text default
+    let text = quality(|media_type| matches!(media_type, "text/plain" | "text/*" | "*/*"));
+
+    json > 0.0 && json >= text
+}
+
+/// Parse a single media range like `image/avif;q=0.8` into its type and quality
+fn media_range(range: &str) -> Option<(&str, f32)> {
+    let mut parts = range.split(';').map(str::trim);
+    let media_type = parts.next().filter(|media_type| !media_type.is_empty())?;
+
+    let quality = parts
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+        .map_or(Some(1.0), |(_, value)| value.trim().parse::<f32>().ok())?;
+
+    Some((media_type, quality))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_picks_the_first_preference_supported() {
+        let accept = "image/avif,image/webp,image/apng,image/svg+xml,image/*,*/*;q=0.8";
+
+        assert_eq!(
+            Some(ImageFormat::Avif),
+            negotiate(accept, &[ImageFormat::Avif, ImageFormat::WebP])
+        );
+        assert_eq!(
+            Some(ImageFormat::WebP),
+            negotiate(accept, &[ImageFormat::WebP, ImageFormat::Avif])
+        );
+    }
+
+    #[test]
+    fn test_negotiate_respects_quality_values() {
+        let accept = "image/avif;q=0.5, image/webp;q=0.9, */*;q=0.1";
+
+        assert_eq!(
+            Some(ImageFormat::WebP),
+            negotiate(accept, &[ImageFormat::Avif, ImageFormat::WebP])
+        );
+        assert_eq!(
+            None,
+            negotiate("image/avif;q=0, image/*", &[ImageFormat::Avif])
+        );
+    }
+
+    #[test]
+    fn test_negotiate_ignores_wildcards() {
+        assert_eq!(
+            None,
+            negotiate("image/*,*/*;q=0.8", &[ImageFormat::Avif, ImageFormat::WebP])
+        );
+    }
+
+    #[test]
+    fn test_wants_json_only_when_json_is_preferred() {
+        assert!(wants_json("application/json"));
+        assert!(wants_json("application/problem+json, */*"));
+        assert!(wants_json("text/plain;q=0.5, application/json"));
+        assert!(!wants_json("*/*"));
+        assert!(!wants_json("text/html,application/xhtml+xml,*/*;q=0.8"));
+        assert!(!wants_json("application/json;q=0.5, text/plain"));
+        assert!(!wants_json("application/json;q=0"));
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    net::IpAddr,
+    str::FromStr,
+};
+use url::{Host, Url};
+
+/// Pattern matching the hosts images can (or can not) be fetched from
+#[derive(Clone, PartialEq, Debug)]
+pub enum HostPattern {
+    /// exact domain or IP address e.g. `images.example.com`
+    Exact(Host),
+    /// any subdomain of a domain e.g. `*.cdn.example.com`
+    Wildcard(String),
+    /// a domain and all of its subdomains e.g. `.example.com`
+    Suffix(String),
+    /// range of IP addresses in CIDR notation e.g. `10.0.0.0/8`
+    Cidr(IpAddr, u8),
+}
+
+impl HostPattern {
+    pub fn matches(&self, host: &Host) -> bool {
+        match (self, host) {
+            (Self::Exact(pattern), host) => pattern == host,
+            (Self::Wildcard(domain), Host::Domain(host)) => host
+                .strip_suffix(domain.as_str())
+                .map(|subdomain| !subdomain.is_empty())
+                .unwrap_or(false),
+            (Self::Suffix(domain), Host::Domain(host)) => {
+                host == domain || host.ends_with(&format!(".{}", domain))
+            }
+            (Self::Cidr(network, prefix), Host::Ipv4(address)) => {
+                contains(network, *prefix, &IpAddr::V4(*address))
+            }
+            (Self::Cidr(network, prefix), Host::Ipv6(address)) => {
+                contains(network, *prefix, &IpAddr::V6(*address))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Check if an IP address is inside the `network/prefix` range
+pub fn contains(network: &IpAddr, prefix: u8, address: &IpAddr) -> bool {
+    match (network, address) {
+        (IpAddr::V4(network), IpAddr::V4(address)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(*network) & mask == u32::from(*address) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(address)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(*network) & mask == u128::from(*address) & mask
+        }
+        _ => false,
+    }
+}
+
+impl FromStr for HostPattern {
+    type Err = InvalidHostPattern;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidHostPattern(pattern.to_string());
+        let domain = |domain: &str| match Host::parse(domain) {
+            Ok(Host::Domain(domain)) => Ok(domain),
+            _ => Err(invalid()),
+        };
+
+        if let Some(domain_pattern) = pattern.strip_prefix("*.") {
+            return domain(domain_pattern).map(|domain| Self::Wildcard(format!(".{}", domain)));
+        }
+
+        if let Some(domain_pattern) = pattern.strip_prefix('.') {
+            return domain(domain_pattern).map(Self::Suffix);
+        }
+
+        if let Some((network, prefix)) = pattern.split_once('/') {
+            let network = network.parse::<IpAddr>().map_err(|_| invalid())?;
+            let prefix = prefix.parse::<u8>().map_err(|_| invalid())?;
+            let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+
+            if prefix > max_prefix {
+                return Err(invalid());
+            }
+
+            return Ok(Self::Cidr(network, prefix));
+        }
+
+        let host = match pattern.parse::<IpAddr>() {
+            Ok(IpAddr::V4(address)) => Host::Ipv4(address),
+            Ok(IpAddr::V6(address)) => Host::Ipv6(address),
+            Err(_) => Host::parse(pattern).map_err(|_| invalid())?,
+        };
+
+        Ok(Self::Exact(host))
+    }
+}
+
+/// Rule matching the source URLs images can (or can not) be fetched from
+///
+/// Either a bare host pattern (e.g. `.example.com`) or a URL that also constrains the scheme,
+/// port and path prefix (e.g. `https://assets.example.com/public/`)
+#[derive(Clone, PartialEq, Debug)]
+pub struct SourceRule {
+    scheme: Option<String>,
+    host: HostPattern,
+    port: Option<u16>,
+    path: Option<String>,
+}
+
+impl SourceRule {
+    pub fn matches(&self, url: &Url) -> bool {
+        let host = match url.host() {
+            Some(host) => host.to_owned(),
+            None => return false,
+        };
+
+        self.host.matches(&host)
+            && self
+                .scheme
+                .as_ref()
+                .map(|scheme| scheme == url.scheme())
+                .unwrap_or(true)
+            && self
+                .port
+                .map(|port| Some(port) == url.port_or_known_default())
+                .unwrap_or(true)
+            && self
+                .path
+                .as_ref()
+                .map(|prefix| within(url.path(), prefix))
+                .unwrap_or(true)
+    }
+}
+
+/// Check if a path is the prefix itself or nested inside it
+///
+/// `/public` covers `/public` and `/public/image.jpg` but not `/publicity.jpg`
+fn within(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .map(|rest| prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/'))
+        .unwrap_or(false)
+}
+
+impl From<HostPattern> for SourceRule {
+    fn from(host: HostPattern) -> Self {
+        Self {
+            scheme: None,
+            host,
+            port: None,
+            path: None,
+        }
+    }
+}
+
+impl FromStr for SourceRule {
+    type Err = InvalidHostPattern;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidHostPattern(rule.to_string());
+
+        let (scheme, rest) = match rule.split_once("://") {
+            Some((scheme, rest)) => (scheme.to_ascii_lowercase(), rest),
+            None => return HostPattern::from_str(rule).map(Self::from),
+        };
+
+        let default_port = match scheme.as_str() {
+            "http" => 80,
+            "https" => 443,
+            _ => return Err(invalid()),
+        };
+
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+
+        let (host, port) = match authority.rsplit_once(':') {
+            // the colons of an IPv6 address like `[::1]` are not a port
+            Some((host, port)) if !port.ends_with(']') => {
+                (host, port.parse::<u16>().map_err(|_| invalid())?)
+            }
+            _ => (authority, default_port),
+        };
+
+        let host = host.parse::<HostPattern>().map_err(|_| invalid())?;
+
+        Ok(Self {
+            scheme: Some(scheme),
+            host,
+            port: Some(port),
+            path: Some(path.to_string()).filter(|path| path != "/"),
+        })
+    }
+}
+
+/// Comma separated list of source rules
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct HostList {
+    rules: Vec<SourceRule>,
+}
+
+impl HostList {
+    pub fn parse(list: &str) -> Result<Self, InvalidHostPattern> {
+        let rules = list
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(SourceRule::from_str)
+            .collect::<Result<Vec<SourceRule>, InvalidHostPattern>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn matches(&self, url: &Url) -> bool {
+        self.rules.iter().any(|rule| rule.matches(url))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidHostPattern(pub String);
+
+impl Display for InvalidHostPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Invalid Host Pattern: {}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host(host: &str) -> Url {
+        Url::parse(&format!("https://{}/image.jpg", host)).unwrap()
+    }
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn test_exact_patterns_only_match_the_same_host() {
+        let list = HostList::parse("images.example.com, 192.168.1.10").unwrap();
+
+        assert!(list.matches(&host("images.example.com")));
+        assert!(list.matches(&host("192.168.1.10")));
+        assert!(!list.matches(&host("cdn.images.example.com")));
+        assert!(!list.matches(&host("192.168.1.11")));
+    }
+
+    #[test]
+    fn test_wildcard_patterns_match_subdomains_only() {
+        let list = HostList::parse("*.cdn.example.com").unwrap();
+
+        assert!(list.matches(&host("tenant.cdn.example.com")));
+        assert!(list.matches(&host("a.tenant.cdn.example.com")));
+        assert!(!list.matches(&host("cdn.example.com")));
+        assert!(!list.matches(&host("evilcdn.example.com")));
+    }
+
+    #[test]
+    fn test_suffix_patterns_match_the_domain_and_subdomains() {
+        let list = HostList::parse(".example.com").unwrap();
+
+        assert!(list.matches(&host("example.com")));
+        assert!(list.matches(&host("images.example.com")));
+        assert!(!list.matches(&host("notexample.com")));
+    }
+
+    #[test]
+    fn test_cidr_patterns_match_ip_ranges() {
+        let list = HostList::parse("10.0.0.0/8, 2001:db8::/32").unwrap();
+
+        assert!(list.matches(&host("10.20.30.40")));
+        assert!(list.matches(&host("[2001:db8::1]")));
+        assert!(!list.matches(&host("11.0.0.1")));
+        assert!(!list.matches(&host("[2001:db9::1]")));
+        assert!(!list.matches(&host("example.com")));
+    }
+
+    #[test]
+    fn test_invalid_patterns_are_reported() {
+        assert!(HostList::parse("example.com, exa mple.com").is_err());
+        assert!(HostList::parse("*.").is_err());
+        assert!(HostList::parse("10.0.0.0/33").is_err());
+        assert!(HostList::parse("10.0.0/8").is_err());
+        assert!(HostList::parse("*.10.0.0.1").is_err());
+        assert!(HostList::parse("ftp://example.com").is_err());
+        assert!(HostList::parse("https://example.com:https/").is_err());
+    }
+
+    #[test]
+    fn test_host_patterns_allow_any_scheme_port_and_path() {
+        let list = HostList::parse("example.com").unwrap();
+
+        assert!(list.matches(&url("http://example.com/uploads/a.jpg")));
+        assert!(list.matches(&url("https://example.com:8443/a.jpg")));
+    }
+
+    #[test]
+    fn test_url_rules_constrain_scheme_port_and_path_prefix() {
+        let list = HostList::parse("https://*.example.com/public/").unwrap();
+
+        assert!(list.matches(&url("https://assets.example.com/public/a.jpg")));
+        assert!(list.matches(&url("https://assets.example.com:443/public/nested/a.jpg")));
+        assert!(!list.matches(&url("http://assets.example.com/public/a.jpg")));
+        assert!(!list.matches(&url("https://assets.example.com:8443/public/a.jpg")));
+        assert!(!list.matches(&url("https://assets.example.com/uploads/a.jpg")));
+        assert!(!list.matches(&url("https://assets.example.com/public/../uploads/a.jpg")));
+    }
+
+    #[test]
+    fn test_url_rules_match_whole_path_segments() {
+        let list = HostList::parse("http://[::1]:8080/public").unwrap();
+
+        assert!(list.matches(&url("http://[::1]:8080/public")));
+        assert!(list.matches(&url("http://[::1]:8080/public/a.jpg")));
+        assert!(!list.matches(&url("http://[::1]:8080/publicity.jpg")));
+        assert!(!list.matches(&url("http://[::1]/public/a.jpg")));
+    }
+}
@@ -1,78 +1,275 @@
-use actix_web::http::StatusCode;
+use actix_web::http::{header, StatusCode};
 use actix_web::web::Bytes;
+use awc::error::{ConnectError, PayloadError, SendRequestError};
 use awc::{Client as ActixWebClient, Connector};
 use openssl::ssl::{SslConnector, SslMethod};
+use rand::Rng;
 use std::{
-    collections::HashSet,
+    cell::RefCell,
+    collections::HashMap,
     fmt::{Display, Formatter, Result as FmtResult},
+    net::IpAddr,
+    rc::Rc,
+    time::Duration,
 };
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, timeout};
 use url::{Host, Url};
 
+use super::allowlist::HostList;
+use super::middleware::errors::ErrorCode;
+use super::resolver::{AddressList, BlockedAddress, SafeResolver};
+use crate::Configuration;
+
 static USER_AGENT: &str = "rusty-resizer";
-const MAX_ALLOWED_BYTES: usize = 20_000_000;
 
-pub struct Client<'app> {
+/// Upstream connection pool settings
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ConnectionPool {
+    /// maximum number of open connections (`0` for no limit)
+    pub size: usize,
+    /// seconds an idle connection is kept alive for reuse
+    pub idle_timeout: u64,
+    /// maximum number of concurrent requests to a single origin (`0` for no limit)
+    pub per_origin: usize,
+}
+
+/// Upstream request timeouts (in seconds)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Timeouts {
+    /// resolving the host and establishing the connection (and, separately, the TLS handshake)
+    pub connect: u64,
+    /// sending the request and receiving the response headers
+    pub headers: u64,
+    /// reading the whole response body
+    pub body: u64,
+}
+
+/// Upstream retries for transient failures (connect errors and `502`, `503` & `504` responses)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Retries {
+    /// maximum number of retries after the first attempt (`0` disables retries)
+    pub attempts: u8,
+    /// milliseconds the backoff starts from before doubling on every retry
+    pub backoff: u64,
+}
+
+impl Retries {
+    /// Random delay ("full jitter") before a retry so retries from many requests are spread out
+    pub fn delay(&self, retry: u8) -> Duration {
+        Duration::from_millis(rand::thread_rng().gen_range(0..=self.max_delay(retry)))
+    }
+
+    /// Longest delay (in milliseconds) before a retry
+    fn max_delay(&self, retry: u8) -> u64 {
+        self.backoff.saturating_mul(1 << retry.min(16))
+    }
+}
+
+/// Upstream HTTP client shared by every request handled by a worker
+///
+/// awc clients are not thread safe so each worker owns one (with its own connection pool)
+pub struct Client {
     client: ActixWebClient,
     user_agent: &'static str,
-    allowed_hosts: &'app HashSet<Host>,
+    allowed_hosts: HostList,
+    denied_hosts: HostList,
+    blocked_addresses: AddressList,
+    max_redirects: u8,
+    max_bytes: usize,
+    body_timeout: Duration,
+    retries: Retries,
+    per_origin: usize,
+    origins: RefCell<HashMap<String, Rc<Semaphore>>>,
 }
 
-impl<'app> Client<'app> {
-    pub fn new(allowed_hosts: &'app HashSet<Host>) -> Self {
+impl Client {
+    pub fn new(configuration: &Configuration) -> Self {
         let user_agent = USER_AGENT;
         let ssl_builder = SslConnector::builder(SslMethod::tls()).unwrap();
+        let pool = configuration.connection_pool;
+        let timeouts = configuration.timeouts;
 
         let client = ActixWebClient::builder()
-            .connector(Connector::new().openssl(ssl_builder.build()))
+            .connector(
+                Connector::new()
+                    .openssl(ssl_builder.build())
+                    .resolver(SafeResolver::new(configuration.blocked_addresses.clone()))
+                    .limit(pool.size)
+                    .conn_keep_alive(Duration::from_secs(pool.idle_timeout))
+                    .timeout(Duration::from_secs(timeouts.connect))
+                    .handshake_timeout(Duration::from_secs(timeouts.connect)),
+            )
+            .timeout(Duration::from_secs(timeouts.headers))
+            // redirects are followed by `get` so every hop can be validated
+            .disable_redirects()
             .finish();
         Self {
             client,
             user_agent,
-            allowed_hosts,
+            allowed_hosts: configuration.allowed_hosts.clone(),
+            denied_hosts: configuration.denied_hosts.clone(),
+            blocked_addresses: configuration.blocked_addresses.clone(),
+            max_redirects: configuration.max_redirects,
+            max_bytes: configuration.max_source_bytes,
+            body_timeout: Duration::from_secs(timeouts.body),
+            retries: configuration.retries,
+            per_origin: pool.per_origin,
+            origins: RefCell::new(HashMap::new()),
         }
     }
 
     pub async fn get(&self, url: &str) -> Result<Bytes, ClientError> {
-        self.validate_host(url)?;
-
-        let mut request = self
-            .client
-            .get(url)
-            .append_header(("User-Agent", self.user_agent))
-            .send()
-            .await
-            .map_err(|_| ClientError::InvalidRequest)?;
-
-        match request.status() {
-            StatusCode::OK => request
-                .body()
-                .limit(MAX_ALLOWED_BYTES)
-                .await
-                .map_err(|_| ClientError::InvalidPayload),
-            StatusCode::NOT_FOUND => Err(ClientError::NotFound),
-            StatusCode::FORBIDDEN => Err(ClientError::InaccessibleImage),
-            _ => Err(ClientError::InvalidRequest),
+        let mut url = Url::parse(url).map_err(|_| ClientError::InvalidRequest)?;
+
+        for _ in 0..=self.max_redirects {
+            self.validate_host(&url)?;
+
+            // hold a slot for the origin until the whole body has been read
+            let origin = self.origin(&url);
+            let _permit = match &origin {
+                Some(origin) => Some(
+                    origin
+                        .acquire()
+                        .await
+                        .map_err(|_| ClientError::InvalidRequest)?,
+                ),
+                None => None,
+            };
+
+            let mut retry = 0;
+            let mut request = loop {
+                let response = self
+                    .client
+                    .get(url.as_str())
+                    .append_header(("User-Agent", self.user_agent))
+                    .send()
+                    .await;
+
+                // only failures that happen before the image is served are safe to retry
+                let retryable = match &response {
+                    Ok(response) => matches!(
+                        response.status(),
+                        StatusCode::BAD_GATEWAY
+                            | StatusCode::SERVICE_UNAVAILABLE
+                            | StatusCode::GATEWAY_TIMEOUT
+                    ),
+                    Err(SendRequestError::Connect(ConnectError::Resolver(err))) => {
+                        !err.is::<BlockedAddress>()
+                    }
+                    Err(SendRequestError::Connect(_)) => true,
+                    Err(_) => false,
+                };
+
+                if !retryable || retry >= self.retries.attempts {
+                    break response?;
+                }
+
+                sleep(self.retries.delay(retry)).await;
+                retry += 1;
+            };
+
+            match request.status() {
+                StatusCode::OK => {
+                    // reject oversized images up front instead of downloading them first
+                    let content_length = request
+                        .headers()
+                        .get(header::CONTENT_LENGTH)
+                        .and_then(|length| length.to_str().ok())
+                        .and_then(|length| length.parse::<usize>().ok());
+
+                    if content_length
+                        .map(|length| length > self.max_bytes)
+                        .unwrap_or(false)
+                    {
+                        return Err(ClientError::PayloadTooLarge);
+                    }
+
+                    let body = request.body().limit(self.max_bytes);
+
+                    return match timeout(self.body_timeout, body).await {
+                        Ok(Ok(body)) => Ok(body),
+                        Ok(Err(PayloadError::Overflow)) => Err(ClientError::PayloadTooLarge),
+                        Ok(Err(_)) => Err(ClientError::InvalidPayload),
+                        Err(_) => Err(ClientError::Timeout),
+                    };
+                }
+                StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT => {
+                    url = request
+                        .headers()
+                        .get(header::LOCATION)
+                        .and_then(|location| location.to_str().ok())
+                        .and_then(|location| url.join(location).ok())
+                        .ok_or(ClientError::UpstreamFailure)?;
+                }
+                StatusCode::NOT_FOUND | StatusCode::GONE => return Err(ClientError::NotFound),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                    return Err(ClientError::InaccessibleImage)
+                }
+                _ => return Err(ClientError::UpstreamFailure),
+            }
         }
+
+        Err(ClientError::TooManyRedirects)
+    }
+
+    /// Semaphore capping the concurrent requests to the origin (scheme, host & port) of a URL
+    fn origin(&self, url: &Url) -> Option<Rc<Semaphore>> {
+        if self.per_origin == 0 {
+            return None;
+        }
+
+        let mut origins = self.origins.borrow_mut();
+        // forget origins without requests in flight so the map does not grow without bounds
+        origins.retain(|_, semaphore| Rc::strong_count(semaphore) > 1);
+
+        let semaphore = origins
+            .entry(url.origin().ascii_serialization())
+            .or_insert_with(|| Rc::new(Semaphore::new(self.per_origin)));
+
+        Some(Rc::clone(semaphore))
     }
 
-    fn validate_host(&self, url: &str) -> Result<(), ClientError> {
-        let url = Url::parse(url).map_err(|_| ClientError::InvalidRequest)?;
+    fn validate_host(&self, url: &Url) -> Result<(), ClientError> {
         let host = url.host().ok_or(ClientError::InvalidRequest)?;
 
-        if self.allowed_hosts.contains(&host.to_owned()) {
-            return Ok(());
+        if !self.allowed_hosts.matches(url) || self.denied_hosts.matches(url) {
+            return Err(ClientError::BlockedHost);
         }
 
-        Err(ClientError::BlockedHost)
+        // IP addresses are connected to directly without going through the resolver
+        let address = match host {
+            Host::Ipv4(address) => Some(IpAddr::V4(address)),
+            Host::Ipv6(address) => Some(IpAddr::V6(address)),
+            Host::Domain(_) => None,
+        };
+
+        if address
+            .map(|address| self.blocked_addresses.contains(&address))
+            .unwrap_or(false)
+        {
+            return Err(ClientError::BlockedAddress);
+        }
+
+        Ok(())
     }
 }
 
 pub enum ClientError {
     InvalidRequest,
     InvalidPayload,
+    PayloadTooLarge,
     NotFound,
     BlockedHost,
+    BlockedAddress,
     InaccessibleImage,
+    TooManyRedirects,
+    UpstreamFailure,
+    Timeout,
 }
 
 impl ClientError {
@@ -80,9 +277,29 @@ impl ClientError {
         match self {
             Self::InvalidRequest => "Invalid Request For Image",
             Self::InvalidPayload => "Invalid Image Payload",
+            Self::PayloadTooLarge => "Image Payload Is Too Large",
             Self::NotFound => "Image Not Found",
             Self::BlockedHost => "Image Host Is Not Allowed",
+            Self::BlockedAddress => "Image Host Address Is Not Allowed",
             Self::InaccessibleImage => "Inaccessible Image",
+            Self::TooManyRedirects => "Too Many Redirects For Image",
+            Self::UpstreamFailure => "Image Host Failed To Serve Image",
+            Self::Timeout => "Timed Out Fetching Image",
+        }
+    }
+
+    /// Status telling clients apart from bad requests and failures of the image host
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidRequest => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::BlockedHost | Self::BlockedAddress => StatusCode::FORBIDDEN,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::InvalidPayload
+            | Self::InaccessibleImage
+            | Self::TooManyRedirects
+            | Self::UpstreamFailure => StatusCode::BAD_GATEWAY,
+            Self::Timeout => StatusCode::GATEWAY_TIMEOUT,
         }
     }
 }
@@ -92,3 +309,97 @@ impl Display for ClientError {
         write!(f, "{}", self.message())
     }
 }
+
+impl ErrorCode for ClientError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest => "invalid_source",
+            Self::InvalidPayload => "invalid_payload",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::NotFound => "image_not_found",
+            Self::BlockedHost => "host_not_allowed",
+            Self::BlockedAddress => "address_not_allowed",
+            Self::InaccessibleImage => "image_inaccessible",
+            Self::TooManyRedirects => "too_many_redirects",
+            Self::UpstreamFailure => "upstream_failure",
+            Self::Timeout => "upstream_timeout",
+        }
+    }
+
+    fn parameter(&self) -> Option<&str> {
+        Some("source")
+    }
+}
+
+impl From<SendRequestError> for ClientError {
+    fn from(err: SendRequestError) -> Self {
+        match err {
+            SendRequestError::Connect(ConnectError::Resolver(err))
+                if err.is::<BlockedAddress>() =>
+            {
+                Self::BlockedAddress
+            }
+            SendRequestError::Timeout | SendRequestError::Connect(ConnectError::Timeout) => {
+                Self::Timeout
+            }
+            SendRequestError::Url(_) => Self::InvalidRequest,
+            _ => Self::UpstreamFailure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delays_grow_exponentially_up_to_a_cap() {
+        let retries = Retries {
+            attempts: 3,
+            backoff: 100,
+        };
+
+        assert_eq!(100, retries.max_delay(0));
+        assert_eq!(200, retries.max_delay(1));
+        assert_eq!(800, retries.max_delay(3));
+        assert_eq!(retries.max_delay(16), retries.max_delay(u8::MAX));
+        assert!(retries.delay(2) <= Duration::from_millis(400));
+    }
+
+    #[actix_rt::test]
+    async fn test_origins_share_a_semaphore_per_scheme_host_and_port() {
+        let configuration =
+            Configuration::new(String::from("test"), String::from("x.com"), 2880, 60, 50)
+                .unwrap()
+                .with_connection_pool(ConnectionPool {
+                    size: 10,
+                    idle_timeout: 60,
+                    per_origin: 2,
+                });
+        let client = Client::new(&configuration);
+        let origin = |url: &str| client.origin(&Url::parse(url).unwrap()).unwrap();
+
+        let first = origin("https://x.com/a.jpg");
+        assert!(Rc::ptr_eq(&first, &origin("https://x.com:443/b.jpg")));
+        assert!(!Rc::ptr_eq(&first, &origin("http://x.com/a.jpg")));
+        assert!(!Rc::ptr_eq(&first, &origin("https://x.com:8443/a.jpg")));
+        assert_eq!(2, first.available_permits());
+    }
+
+    #[actix_rt::test]
+    async fn test_origins_are_unlimited_without_a_per_origin_limit() {
+        let configuration =
+            Configuration::new(String::from("test"), String::from("x.com"), 2880, 60, 50)
+                .unwrap()
+                .with_connection_pool(ConnectionPool {
+                    size: 10,
+                    idle_timeout: 60,
+                    per_origin: 0,
+                });
+        let client = Client::new(&configuration);
+
+        assert!(client
+            .origin(&Url::parse("https://x.com/a.jpg").unwrap())
+            .is_none());
+    }
+}
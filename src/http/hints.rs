@@ -0,0 +1,83 @@
+use actix_web::HttpRequest;
+
+pub static SEC_CH_DPR: &str = "Sec-CH-DPR";
+pub static SEC_CH_WIDTH: &str = "Sec-CH-Width";
+pub static SEC_CH_VIEWPORT_WIDTH: &str = "Sec-CH-Viewport-Width";
+pub static SAVE_DATA: &str = "Save-Data";
+
+/// Response header used to ask browsers to send the hints on subsequent requests
+pub static ACCEPT_CH: &str = "Accept-CH";
+pub static ACCEPTED_HINTS: &str = "Sec-CH-DPR, Sec-CH-Width, Sec-CH-Viewport-Width";
+
+/// Client Hints sent along with an incoming request
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct ClientHints {
+    pub dpr: Option<f32>,
+    /// intended display width of the image in physical pixels
+    pub width: Option<f32>,
+    /// width of the viewport in CSS pixels
+    pub viewport_width: Option<f32>,
+    pub save_data: bool,
+}
+
+impl ClientHints {
+    pub fn from_request(request: &HttpRequest) -> Self {
+        Self {
+            dpr: number(request, SEC_CH_DPR),
+            width: number(request, SEC_CH_WIDTH),
+            viewport_width: number(request, SEC_CH_VIEWPORT_WIDTH),
+            save_data: request
+                .headers()
+                .get(SAVE_DATA)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().eq_ignore_ascii_case("on"))
+                .unwrap_or(false),
+        }
+    }
+}
+
+fn number(request: &HttpRequest, name: &str) -> Option<f32> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<f32>().ok())
+        .filter(|value| value.is_finite() && *value > 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn test_client_hints_are_parsed_from_headers() {
+        let request = TestRequest::default()
+            .insert_header((SEC_CH_DPR, "2.5"))
+            .insert_header((SEC_CH_WIDTH, "640"))
+            .insert_header((SEC_CH_VIEWPORT_WIDTH, "1280"))
+            .insert_header((SAVE_DATA, "on"))
+            .to_http_request();
+
+        assert_eq!(
+            ClientHints {
+                dpr: Some(2.5),
+                width: Some(640.0),
+                viewport_width: Some(1280.0),
+                save_data: true,
+            },
+            ClientHints::from_request(&request)
+        );
+    }
+
+    #[test]
+    fn test_client_hints_ignore_missing_or_invalid_headers() {
+        let request = TestRequest::default()
+            .insert_header((SEC_CH_DPR, "-1"))
+            .insert_header((SEC_CH_WIDTH, "wide"))
+            .to_http_request();
+
+        assert_eq!(ClientHints::default(), ClientHints::from_request(&request));
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fmt::Display,
+    future::{ready, Ready},
+};
+
+use actix_http::{
+    header::{HeaderName, HeaderValue},
+    StatusCode,
+};
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    Error, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+use serde::Serialize;
+
+use crate::http::accept;
+
+pub static X_REQUEST_ID: &str = "x-request-id";
+
+/// Error that can be described to clients with a stable machine readable code
+pub trait ErrorCode: Display {
+    /// snake case identifier for the kind of error (e.g. `image_not_found`)
+    fn code(&self) -> &'static str;
+
+    /// query parameter responsible for the error (if any)
+    fn parameter(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Details of an error response kept in its extensions until `ErrorFormat` renders them
+#[derive(Clone)]
+struct ErrorDetails {
+    code: &'static str,
+    message: String,
+    parameter: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+    parameter: Option<String>,
+    request_id: String,
+}
+
+/// Build a plain text error response that `ErrorFormat` can turn into JSON
+pub fn error_response<E: ErrorCode + ?Sized>(status: StatusCode, error: &E) -> HttpResponse {
+    let message = error.to_string();
+    let mut response = HttpResponse::build(status).body(message.clone());
+
+    response.extensions_mut().insert(ErrorDetails {
+        code: error.code(),
+        message,
+        parameter: error.parameter().map(String::from),
+    });
+
+    response
+}
+
+/// Factory to create an ErrorFormatMiddleware that renders error responses as JSON for clients that ask for it.
+pub struct ErrorFormat;
+
+impl<S, B> Transform<S, ServiceRequest> for ErrorFormat
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ErrorFormatMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ErrorFormatMiddleware { service }))
+    }
+}
+
+/// Middleware that tags every error response built with `error_response` with a request ID and,
+/// when the `Accept` header prefers JSON, replaces the plain text body with:
+///
+/// `{"code": "...", "message": "...", "parameter": "...", "request_id": "..."}`
+///
+/// The request ID is taken from the incoming `X-Request-Id` header when present.
+pub struct ErrorFormatMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for ErrorFormatMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let json = accept::prefers_json(req.request());
+        let request_id = req
+            .headers()
+            .get(X_REQUEST_ID)
+            .filter(|request_id| request_id.len() <= 128)
+            .and_then(|request_id| request_id.to_str().ok())
+            .map(String::from)
+            .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+        let future = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = future.await?;
+
+            let details = res.response().extensions().get::<ErrorDetails>().cloned();
+            let details = match details {
+                Some(details) => details,
+                None => return Ok(res.map_into_left_body()),
+            };
+
+            let header = HeaderValue::from_str(&request_id)
+                .expect("Request ID should be a valid header value");
+
+            if !json {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(X_REQUEST_ID), header);
+                return Ok(res.map_into_left_body());
+            }
+
+            let response = HttpResponse::build(res.status())
+                .insert_header((X_REQUEST_ID, header))
+                .json(ErrorBody {
+                    code: details.code,
+                    message: details.message,
+                    parameter: details.parameter,
+                    request_id,
+                });
+
+            Ok(res.into_response(response).map_into_right_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_http::header;
+    use actix_service::{IntoService, Service, Transform};
+    use actix_web::{body::to_bytes, test::TestRequest};
+    use futures_util::future::ok;
+    use std::fmt::{Formatter, Result as FmtResult};
+
+    use super::*;
+
+    struct TestError;
+
+    impl Display for TestError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+            write!(f, "Test Error")
+        }
+    }
+
+    impl ErrorCode for TestError {
+        fn code(&self) -> &'static str {
+            "test_error"
+        }
+
+        fn parameter(&self) -> Option<&str> {
+            Some("source")
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_error_format_keeps_plain_text_by_default() {
+        let srv = |req: ServiceRequest| {
+            ok(req.into_response(error_response(StatusCode::NOT_FOUND, &TestError)))
+        };
+        let srv = ErrorFormat.new_transform(srv.into_service()).await.unwrap();
+
+        let req = TestRequest::default()
+            .insert_header((X_REQUEST_ID, "abc"))
+            .to_srv_request();
+        let res = srv.call(req).await.unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        assert_eq!("abc", res.headers().get(X_REQUEST_ID).unwrap());
+        let body = to_bytes(res.into_body()).await.unwrap();
+        assert_eq!("Test Error", body);
+    }
+
+    #[actix_rt::test]
+    async fn test_error_format_renders_json_when_accepted() {
+        let srv = |req: ServiceRequest| {
+            ok(req.into_response(error_response(StatusCode::NOT_FOUND, &TestError)))
+        };
+        let srv = ErrorFormat.new_transform(srv.into_service()).await.unwrap();
+
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT, "application/json"))
+            .insert_header((X_REQUEST_ID, "abc"))
+            .to_srv_request();
+        let res = srv.call(req).await.unwrap();
+
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        let body = to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            r#"{"code":"test_error","message":"Test Error","parameter":"source","request_id":"abc"}"#,
+            body
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_error_format_ignores_other_responses() {
+        let srv = |req: ServiceRequest| ok(req.into_response(HttpResponse::Ok().body("ok")));
+        let srv = ErrorFormat.new_transform(srv.into_service()).await.unwrap();
+
+        let req = TestRequest::default()
+            .insert_header((header::ACCEPT, "application/json"))
+            .to_srv_request();
+        let res = srv.call(req).await.unwrap();
+
+        assert!(res.headers().get(X_REQUEST_ID).is_none());
+        let body = to_bytes(res.into_body()).await.unwrap();
+        assert_eq!("ok", body);
+    }
+}
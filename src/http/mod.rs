@@ -1,4 +1,11 @@
 pub use client::Client;
+pub use hints::ClientHints;
 
+pub mod accept;
+pub mod allowlist;
 pub mod client;
+pub mod hints;
 pub mod middleware;
+pub mod query;
+pub mod resolver;
+pub mod signature;
@@ -0,0 +1,188 @@
+use actix_http::StatusCode;
+use actix_web::{
+    error::{Error, InternalError, QueryPayloadError},
+    HttpRequest,
+};
+use serde::{de, Deserialize, Deserializer};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use url::form_urlencoded;
+
+use super::middleware::errors::{error_response, ErrorCode};
+
+/// Answer query strings that can not be deserialized with a `400` naming the parameter at fault
+pub fn error_handler(err: QueryPayloadError, _request: &HttpRequest) -> Error {
+    let response = error_response(StatusCode::BAD_REQUEST, &QueryError::from(&err));
+
+    InternalError::from_response(err, response).into()
+}
+
+/// Deserialize an optional parameter naming it in the error when its value is invalid
+///
+/// serde only names missing or unknown fields so a value that fails to parse (e.g. `width=abc`)
+/// would otherwise be reported without the parameter at fault.
+pub fn named<'de, D, T>(parameter: &str, deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map_err(|err| {
+        de::Error::custom(format!("invalid value for field `{}`: {}", parameter, err))
+    })
+}
+
+/// Check that an optional number is finite and within `min..=max`
+pub fn check_range<T: Into<f64>>(
+    parameter: &'static str,
+    value: Option<T>,
+    min: f64,
+    max: f64,
+) -> Result<(), QueryError> {
+    match value.map(Into::into) {
+        Some(value) if !value.is_finite() || value < min || value > max => {
+            Err(QueryError::OutOfRange(parameter, min, max))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check that every parameter of a query string is one of the `known` parameters
+///
+/// Catches typos (e.g. `max_quality` instead of `quality`) that would otherwise be ignored.
+pub fn check_known(query: &str, known: &[&str]) -> Result<(), QueryError> {
+    match form_urlencoded::parse(query.as_bytes()).find(|(name, _)| !known.contains(&name.as_ref()))
+    {
+        Some((name, _)) => Err(QueryError::Unknown(name.into_owned())),
+        None => Ok(()),
+    }
+}
+
+pub enum QueryError {
+    /// query string that could not be deserialized (with the parameter at fault when known)
+    Invalid(String, Option<String>),
+    /// number outside of the `min..=max` range allowed for a parameter
+    OutOfRange(&'static str, f64, f64),
+    /// parameter the endpoint does not accept
+    Unknown(String),
+}
+
+impl From<&QueryPayloadError> for QueryError {
+    fn from(err: &QueryPayloadError) -> Self {
+        let message = err.to_string();
+        // serde names the field in messages like "missing field `source`"
+        let parameter = message
+            .split_once("field `")
+            .and_then(|(_, rest)| rest.split_once('`'))
+            .map(|(parameter, _)| parameter.to_string());
+
+        Self::Invalid(message, parameter)
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Self::Invalid(message, _) => write!(f, "{}", message),
+            Self::OutOfRange(parameter, min, max) => write!(
+                f,
+                "Parameter `{}` Must Be A Number Between {} And {}",
+                parameter, min, max
+            ),
+            Self::Unknown(parameter) => write!(f, "Unknown Parameter `{}`", parameter),
+        }
+    }
+}
+
+impl ErrorCode for QueryError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Invalid(_, _) => "invalid_query",
+            Self::OutOfRange(_, _, _) => "parameter_out_of_range",
+            Self::Unknown(_) => "unknown_parameter",
+        }
+    }
+
+    fn parameter(&self) -> Option<&str> {
+        match self {
+            Self::Invalid(_, parameter) => parameter.as_deref(),
+            Self::OutOfRange(parameter, _, _) => Some(parameter),
+            Self::Unknown(parameter) => Some(parameter),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web::Query;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)]
+    struct Options {
+        source: String,
+        #[serde(default, deserialize_with = "width")]
+        width: Option<usize>,
+    }
+
+    fn width<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+        named("width", deserializer)
+    }
+
+    fn query_error(query: &str) -> QueryError {
+        QueryError::from(&Query::<Options>::from_query(query).unwrap_err())
+    }
+
+    #[test]
+    fn test_query_errors_name_the_parameter_when_known() {
+        assert_eq!(Some("source"), query_error("width=100").parameter());
+        assert_eq!(
+            Some("height"),
+            query_error("source=a.jpg&height=1").parameter()
+        );
+        assert_eq!(
+            Some("width"),
+            query_error("source=a.jpg&width=wide").parameter()
+        );
+    }
+
+    #[test]
+    fn test_named_parameters_are_optional() {
+        let options = Query::<Options>::from_query("source=a.jpg").unwrap();
+        assert_eq!(None, options.width);
+
+        let options = Query::<Options>::from_query("source=a.jpg&width=10").unwrap();
+        assert_eq!(Some(10), options.width);
+    }
+
+    #[test]
+    fn test_check_range_rejects_numbers_outside_of_the_range() {
+        assert!(check_range("width", Some(100.0f32), 1.0, 100.0).is_ok());
+        assert!(check_range::<f32>("width", None, 1.0, 100.0).is_ok());
+        assert!(check_range("width", Some(0.0f32), 1.0, 100.0).is_err());
+        assert!(check_range("width", Some(-5.0f32), 1.0, 100.0).is_err());
+        assert!(check_range("width", Some(f32::NAN), 1.0, 100.0).is_err());
+        assert!(check_range("width", Some(f32::INFINITY), 1.0, 100.0).is_err());
+        assert!(check_range("quality", Some(101u8), 1.0, 100.0).is_err());
+
+        let err = check_range("fx", Some(1.5), 0.0, 1.0).unwrap_err();
+        assert_eq!(
+            "Parameter `fx` Must Be A Number Between 0 And 1",
+            err.to_string()
+        );
+        assert_eq!(Some("fx"), err.parameter());
+    }
+
+    #[test]
+    fn test_check_known_reports_the_first_unknown_parameter() {
+        let known = ["source", "quality"];
+
+        assert!(check_known("source=a.jpg&quality=80", &known).is_ok());
+        assert!(check_known("", &known).is_ok());
+        assert!(matches!(
+            check_known("source=a.jpg&max_quality=80", &known),
+            Err(QueryError::Unknown(parameter)) if parameter == "max_quality"
+        ));
+    }
+}
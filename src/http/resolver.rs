@@ -0,0 +1,136 @@
+use actix_tls::connect::Resolve;
+use futures_util::future::LocalBoxFuture;
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+use tokio::net::lookup_host;
+use url::Host;
+
+use super::allowlist::{self, HostPattern, InvalidHostPattern};
+
+/// Private, loopback, link-local (including cloud metadata endpoints) and other non public ranges
+pub static DEFAULT_BLOCKED_ADDRESSES: &str = "0.0.0.0/8, 10.0.0.0/8, 100.64.0.0/10, 127.0.0.0/8, \
+    169.254.0.0/16, 172.16.0.0/12, 192.0.0.0/24, 192.168.0.0/16, 198.18.0.0/15, 224.0.0.0/4, \
+    240.0.0.0/4, ::/128, ::1/128, fc00::/7, fe80::/10, ff00::/8";
+
+/// Comma separated list of IP addresses and ranges in CIDR notation
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct AddressList {
+    ranges: Vec<(IpAddr, u8)>,
+}
+
+impl AddressList {
+    pub fn parse(list: &str) -> Result<Self, InvalidHostPattern> {
+        let ranges = list
+            .split(',')
+            .map(str::trim)
+            .filter(|range| !range.is_empty())
+            .map(|range| match HostPattern::from_str(range)? {
+                HostPattern::Cidr(network, prefix) => Ok((network, prefix)),
+                HostPattern::Exact(Host::Ipv4(address)) => Ok((IpAddr::V4(address), 32)),
+                HostPattern::Exact(Host::Ipv6(address)) => Ok((IpAddr::V6(address), 128)),
+                _ => Err(InvalidHostPattern(range.to_string())),
+            })
+            .collect::<Result<Vec<(IpAddr, u8)>, InvalidHostPattern>>()?;
+
+        Ok(Self { ranges })
+    }
+
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        // IPv4 addresses mapped into IPv6 (e.g. `::ffff:127.0.0.1`) are checked as IPv4
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*address),
+            IpAddr::V4(_) => *address,
+        };
+
+        self.ranges
+            .iter()
+            .any(|(network, prefix)| allowlist::contains(network, *prefix, &address))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+/// DNS resolver that refuses to connect to hosts resolving to any blocked address
+///
+/// Checking the resolved addresses (instead of the host name) stops allowed host names
+/// with DNS records pointing at internal services from being fetched.
+pub struct SafeResolver {
+    blocked_addresses: AddressList,
+}
+
+impl SafeResolver {
+    pub fn new(blocked_addresses: AddressList) -> Self {
+        Self { blocked_addresses }
+    }
+}
+
+impl Resolve for SafeResolver {
+    fn lookup<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> LocalBoxFuture<'a, Result<Vec<SocketAddr>, Box<dyn Error>>> {
+        Box::pin(async move {
+            let addresses = lookup_host((host, port)).await?.collect::<Vec<_>>();
+
+            if let Some(address) = addresses
+                .iter()
+                .find(|address| self.blocked_addresses.contains(&address.ip()))
+            {
+                return Err(Box::new(BlockedAddress(address.ip())) as Box<dyn Error>);
+            }
+
+            Ok(addresses)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockedAddress(pub IpAddr);
+
+impl Display for BlockedAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Blocked Address: {}", self.0)
+    }
+}
+
+impl Error for BlockedAddress {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn test_default_blocked_addresses() {
+        let blocked = AddressList::parse(DEFAULT_BLOCKED_ADDRESSES).unwrap();
+
+        assert!(blocked.contains(&address("169.254.169.254")));
+        assert!(blocked.contains(&address("10.1.2.3")));
+        assert!(blocked.contains(&address("172.31.255.255")));
+        assert!(blocked.contains(&address("127.0.0.1")));
+        assert!(blocked.contains(&address("::1")));
+        assert!(blocked.contains(&address("fd00:ec2::254")));
+        assert!(blocked.contains(&address("::ffff:192.168.0.1")));
+        assert!(!blocked.contains(&address("172.32.0.1")));
+        assert!(!blocked.contains(&address("185.199.108.133")));
+        assert!(!blocked.contains(&address("2606:50c0:8000::154")));
+    }
+
+    #[test]
+    fn test_address_lists_only_accept_ip_addresses_and_ranges() {
+        assert!(AddressList::parse("10.0.0.1, 10.0.0.0/8, ::1").is_ok());
+        assert!(AddressList::parse("").unwrap().is_empty());
+        assert!(AddressList::parse("example.com").is_err());
+        assert!(AddressList::parse("10.0.0.0/40").is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use openssl::{error::ErrorStack, hash::MessageDigest, memcmp, pkey::PKey, sign::Signer};
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use url::form_urlencoded;
+
+use super::middleware::errors::ErrorCode;
+
+pub static SIGNATURE_PARAMETER: &str = "signature";
+
+/// Verify the HMAC-SHA256 `signature` of a query string against any of the active keys
+///
+/// Accepting multiple keys allows signing keys to be rotated without invalidating existing URLs.
+pub fn verify(query: &str, keys: &[String]) -> Result<(), SignatureError> {
+    let signature = form_urlencoded::parse(query.as_bytes())
+        .find(|(name, _)| name == SIGNATURE_PARAMETER)
+        .map(|(_, signature)| signature.to_ascii_lowercase())
+        .ok_or(SignatureError::Missing)?;
+
+    let canonical = canonical(query);
+
+    // a key that fails to sign matches nothing rather than an empty signature
+    keys.iter()
+        .filter_map(|key| sign(&canonical, key).ok())
+        .any(|expected| {
+            expected.len() == signature.len()
+                && memcmp::eq(expected.as_bytes(), signature.as_bytes())
+        })
+        .then_some(())
+        .ok_or(SignatureError::Invalid)
+}
+
+/// Remaining lifetime of a URL that expires at the `expires` Unix timestamp
+pub fn remaining(expires: u64, now: SystemTime) -> Result<Duration, SignatureError> {
+    UNIX_EPOCH
+        .checked_add(Duration::from_secs(expires))
+        .and_then(|deadline| deadline.duration_since(now).ok())
+        .filter(|remaining| !remaining.is_zero())
+        .ok_or(SignatureError::Expired)
+}
+
+/// Canonical form of a query string that is signed
+///
+/// Every parameter except the `signature` sorted by name (and value) and form url encoded:
+/// `height=100&source=https%3A%2F%2Fexample.com%2Fimage.jpg&width=100`
+pub fn canonical(query: &str) -> String {
+    let mut parameters = form_urlencoded::parse(query.as_bytes())
+        .filter(|(name, _)| name != SIGNATURE_PARAMETER)
+        .collect::<Vec<_>>();
+    parameters.sort();
+
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(parameters)
+        .finish()
+}
+
+/// Lowercase hex encoded HMAC-SHA256 of a canonical query string
+pub fn sign(canonical: &str, key: &str) -> Result<String, ErrorStack> {
+    PKey::hmac(key.as_bytes())
+        .and_then(|key| {
+            let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+            signer.sign_oneshot_to_vec(canonical.as_bytes())
+        })
+        .map(|signature| {
+            signature
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect()
+        })
+}
+
+pub enum SignatureError {
+    Missing,
+    Invalid,
+    Expired,
+}
+
+impl SignatureError {
+    fn message(&self) -> &str {
+        match self {
+            Self::Missing => "Missing Signature",
+            Self::Invalid => "Invalid Signature",
+            Self::Expired => "Expired URL",
+        }
+    }
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl ErrorCode for SignatureError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Missing => "missing_signature",
+            Self::Invalid => "invalid_signature",
+            Self::Expired => "expired_url",
+        }
+    }
+
+    fn parameter(&self) -> Option<&str> {
+        match self {
+            Self::Missing | Self::Invalid => Some(SIGNATURE_PARAMETER),
+            Self::Expired => Some("expires"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed(query: &str, key: &str) -> String {
+        format!(
+            "{}&signature={}",
+            query,
+            sign(&canonical(query), key).expect("Failed to sign query")
+        )
+    }
+
+    #[test]
+    fn test_canonical_query_is_sorted_and_encoded() {
+        assert_eq!(
+            "height=100&source=https%3A%2F%2Fx.com%2Fa.jpg&width=100",
+            canonical("width=100&source=https://x.com/a.jpg&signature=abc&height=100")
+        );
+    }
+
+    #[test]
+    fn test_verify_accepts_signatures_from_any_active_key() {
+        let keys = vec![String::from("new"), String::from("old")];
+        let query = "source=https://x.com/a.jpg&width=100";
+
+        assert!(verify(&signed(query, "new"), &keys).is_ok());
+        assert!(verify(&signed(query, "old"), &keys).is_ok());
+        assert!(verify(&signed(query, "retired"), &keys).is_err());
+    }
+
+    #[test]
+    fn test_remaining_lifetime_of_expiring_urls() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000);
+
+        assert_eq!(
+            Duration::from_secs(500),
+            remaining(1_500, now).unwrap_or_default()
+        );
+        assert!(matches!(
+            remaining(1_000, now),
+            Err(SignatureError::Expired)
+        ));
+        assert!(matches!(remaining(500, now), Err(SignatureError::Expired)));
+    }
+
+    #[test]
+    fn test_verify_rejects_missing_or_tampered_signatures() {
+        let keys = vec![String::from("secret")];
+        let query = signed("source=https://x.com/a.jpg&width=100", "secret");
+
+        assert!(matches!(
+            verify("source=https://x.com/a.jpg&width=100", &keys),
+            Err(SignatureError::Missing)
+        ));
+        assert!(matches!(
+            verify(&query.replace("width=100", "width=9999"), &keys),
+            Err(SignatureError::Invalid)
+        ));
+        assert!(matches!(
+            verify("source=https://x.com/a.jpg&width=100&signature=", &keys),
+            Err(SignatureError::Invalid)
+        ));
+    }
+}
@@ -0,0 +1,133 @@
+use serde::Deserialize;
+
+use super::ImageError;
+
+/// Rectangle cropped out of the source image before it is resized
+///
+/// Parsed from `x,y,width,height` where the values are either pixels or,
+/// when every value is between 0 and 1, fractions of the source image.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(try_from = "String")]
+pub struct Crop {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl Crop {
+    /// Resolve the crop into a pixel region `(x, y, width, height)` of a source image
+    pub fn region(
+        &self,
+        source_width: usize,
+        source_height: usize,
+    ) -> Result<(usize, usize, usize, usize), ImageError> {
+        let fractional = [self.x, self.y, self.width, self.height]
+            .iter()
+            .all(|value| *value <= 1.0);
+
+        let (x, y, width, height) = if fractional {
+            (
+                self.x * source_width as f64,
+                self.y * source_height as f64,
+                self.width * source_width as f64,
+                self.height * source_height as f64,
+            )
+        } else {
+            (self.x, self.y, self.width, self.height)
+        };
+
+        let (x, y, width, height) = (
+            x.round() as usize,
+            y.round() as usize,
+            width.round() as usize,
+            height.round() as usize,
+        );
+
+        // huge values saturate when rounded so the region is checked without overflowing
+        let fits = |offset: usize, length: usize, source: usize| {
+            length > 0 && matches!(offset.checked_add(length), Some(end) if end <= source)
+        };
+
+        if fits(x, width, source_width) && fits(y, height, source_height) {
+            Ok((x, y, width, height))
+        } else {
+            Err(ImageError::InvalidCrop)
+        }
+    }
+}
+
+impl TryFrom<String> for Crop {
+    type Error = &'static str;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = "crop must be four comma separated positive numbers: x,y,width,height";
+
+        let values = value
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| invalid)?;
+
+        match values[..] {
+            [x, y, width, height]
+                if values
+                    .iter()
+                    .all(|value| value.is_finite() && *value >= 0.0) =>
+            {
+                Ok(Crop {
+                    x,
+                    y,
+                    width,
+                    height,
+                })
+            }
+            _ => Err(invalid),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crop_parses_pixels_and_fractions() {
+        let pixels = Crop::try_from(String::from("10,20,300,400")).unwrap();
+        let fractions = Crop::try_from(String::from("0.25, 0.5, 0.5, 0.5")).unwrap();
+
+        assert_eq!(
+            Ok((10, 20, 300, 400)),
+            pixels.region(1000, 1000).map_err(|_| ())
+        );
+        assert_eq!(
+            Ok((250, 500, 500, 500)),
+            fractions.region(1000, 1000).map_err(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_crop_rejects_malformed_values() {
+        assert!(Crop::try_from(String::from("10,20,300")).is_err());
+        assert!(Crop::try_from(String::from("10,20,300,abc")).is_err());
+        assert!(Crop::try_from(String::from("-10,20,300,400")).is_err());
+    }
+
+    #[test]
+    fn test_crop_rejects_regions_outside_the_source() {
+        let crop = Crop::try_from(String::from("800,0,300,400")).unwrap();
+        let empty = Crop::try_from(String::from("0,0,0,0.5")).unwrap();
+
+        assert!(crop.region(1000, 1000).is_err());
+        assert!(empty.region(1000, 1000).is_err());
+    }
+
+    #[test]
+    fn test_crop_rejects_regions_too_large_to_represent() {
+        let offset = Crop::try_from(String::from("1e20,0,10,10")).unwrap();
+        let size = Crop::try_from(String::from("10,0,1e20,10")).unwrap();
+
+        assert!(offset.region(1000, 1000).is_err());
+        assert!(size.region(1000, 1000).is_err());
+    }
+}
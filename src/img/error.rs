@@ -1,9 +1,17 @@
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
+use super::limits::Limit;
+
 pub enum ImageError {
     InvalidImage,
     InvalidFormat,
+    UnsupportedFormat,
     FailedWrite,
+    InvalidColor,
+    InvalidCrop,
+    ImageTooLarge,
+    TimedOut,
+    OutputTooLarge(Limit),
 }
 
 impl ImageError {
@@ -11,7 +19,15 @@ impl ImageError {
         match self {
             Self::InvalidImage => "Invalid Image",
             Self::InvalidFormat => "Invalid Format For Image",
+            Self::UnsupportedFormat => "Image Format Is Not Allowed",
             Self::FailedWrite => "Failed To Write Image",
+            Self::InvalidColor => "Invalid Background Color",
+            Self::InvalidCrop => "Crop Is Outside Of The Image Bounds",
+            Self::ImageTooLarge => "Image Is Too Large",
+            Self::TimedOut => "Timed Out Processing Image",
+            Self::OutputTooLarge(Limit::Width) => "Width Exceeds The Maximum Output Width",
+            Self::OutputTooLarge(Limit::Height) => "Height Exceeds The Maximum Output Height",
+            Self::OutputTooLarge(Limit::Area) => "Dimensions Exceed The Maximum Output Area",
         }
     }
 }
@@ -1,5 +1,5 @@
-use image::ImageFormat;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter, Result as FmtResult};
 
 #[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -19,6 +19,8 @@ pub enum ResizeImageFormat {
     OpenExr,
     Farbfeld,
     Avif,
+    Heic,
+    Jxl,
 }
 
 impl From<ResizeImageFormat> for Option<ImageFormat> {
@@ -38,8 +40,319 @@ impl From<ResizeImageFormat> for Option<ImageFormat> {
             ResizeImageFormat::OpenExr => Some(ImageFormat::OpenExr),
             ResizeImageFormat::Farbfeld => Some(ImageFormat::Farbfeld),
             ResizeImageFormat::Avif => Some(ImageFormat::Avif),
+            ResizeImageFormat::Heic => Some(ImageFormat::Heic),
+            ResizeImageFormat::Jxl => Some(ImageFormat::Jxl),
             // can not convert ResizeImageFormat::Auto into an ImageFormat
             ResizeImageFormat::Auto => None,
         }
     }
 }
+
+const AVIF_BRANDS: [&[u8]; 2] = [b"avif", b"avis"];
+const HEIC_BRANDS: [&[u8]; 8] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+
+/// Image formats understood by ImageMagick that the resizer can read and write
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    WebP,
+    Pnm,
+    Tiff,
+    Tga,
+    Dds,
+    Bmp,
+    Ico,
+    Hdr,
+    OpenExr,
+    Farbfeld,
+    Avif,
+    Heic,
+    Jxl,
+}
+
+impl ImageFormat {
+    /// Parse an ImageMagick format (e.g. `JPEG`) or a file extension (e.g. `jpg`)
+    ///
+    /// ```rust
+    /// # use rusty_resizer::ImageFormat;
+    ///
+    /// assert_eq!(Some(ImageFormat::Jpeg), ImageFormat::from_name("JPEG"));
+    /// assert_eq!(Some(ImageFormat::Jpeg), ImageFormat::from_name("jpg"));
+    /// assert_eq!(Some(ImageFormat::Heic), ImageFormat::from_name("heif"));
+    /// assert_eq!(None, ImageFormat::from_name("mvg"));
+    /// ```
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "gif" => Some(Self::Gif),
+            "webp" => Some(Self::WebP),
+            "pnm" | "pbm" | "pam" | "ppm" | "pgm" => Some(Self::Pnm),
+            "tif" | "tiff" => Some(Self::Tiff),
+            "tga" => Some(Self::Tga),
+            "dds" => Some(Self::Dds),
+            "bmp" => Some(Self::Bmp),
+            "ico" => Some(Self::Ico),
+            "hdr" => Some(Self::Hdr),
+            "exr" => Some(Self::OpenExr),
+            "ff" | "farbfeld" => Some(Self::Farbfeld),
+            "avif" => Some(Self::Avif),
+            "heic" | "heif" => Some(Self::Heic),
+            "jxl" => Some(Self::Jxl),
+            _ => None,
+        }
+    }
+
+    /// Parse a comma separated list of format names (see `from_name`)
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, UnknownFormat> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| Self::from_name(name).ok_or_else(|| UnknownFormat(name.to_string())))
+            .collect()
+    }
+
+    /// Detect the format of an encoded image from its magic bytes
+    ///
+    /// Only the formats the resizer supports are recognized so anything else (e.g. MVG, SVG or
+    /// PostScript) never reaches an ImageMagick coder.
+    ///
+    /// ```rust
+    /// # use rusty_resizer::ImageFormat;
+    ///
+    /// assert_eq!(Some(ImageFormat::Png), ImageFormat::sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+    /// assert_eq!(Some(ImageFormat::Gif), ImageFormat::sniff(b"GIF89a\x01\0\x01\0"));
+    /// assert_eq!(None, ImageFormat::sniff(b"push graphic-context\nviewbox 0 0 640 480"));
+    /// ```
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        let starts_with = |magic: &[u8]| bytes.starts_with(magic);
+
+        if starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if starts_with(b"\xff\xd8\xff") {
+            Some(Self::Jpeg)
+        } else if starts_with(b"GIF87a") || starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else if starts_with(b"RIFF") && bytes.get(8..12) == Some(&b"WEBP"[..]) {
+            Some(Self::WebP)
+        } else if starts_with(b"II*\0") || starts_with(b"MM\0*") {
+            Some(Self::Tiff)
+        } else if starts_with(b"BM") {
+            Some(Self::Bmp)
+        } else if starts_with(b"\0\0\x01\0") {
+            Some(Self::Ico)
+        } else if starts_with(b"DDS ") {
+            Some(Self::Dds)
+        } else if starts_with(b"v/1\x01") {
+            Some(Self::OpenExr)
+        } else if starts_with(b"#?RADIANCE") || starts_with(b"#?RGBE") {
+            Some(Self::Hdr)
+        } else if starts_with(b"farbfeld") {
+            Some(Self::Farbfeld)
+        } else if starts_with(b"\xff\x0a") || starts_with(b"\0\0\0\x0cJXL \r\n\x87\n") {
+            Some(Self::Jxl)
+        } else if bytes.get(4..8) == Some(&b"ftyp"[..]) {
+            Self::sniff_iso_media(bytes)
+        } else if bytes.len() > 2
+            && bytes[0] == b'P'
+            && (b'1'..=b'7').contains(&bytes[1])
+            && bytes[2].is_ascii_whitespace()
+        {
+            Some(Self::Pnm)
+        } else if bytes.ends_with(b"TRUEVISION-XFILE.\0") {
+            Some(Self::Tga)
+        } else {
+            None
+        }
+    }
+
+    /// AVIF and HEIC images are both ISO media files identified by the brands of their `ftyp` box
+    fn sniff_iso_media(bytes: &[u8]) -> Option<Self> {
+        let size = u32::from_be_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+        let brands = bytes.get(8..size.min(bytes.len()))?;
+        // the major brand followed by the minor version and the compatible brands
+        let brands = brands
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(index, _)| *index != 1)
+            .map(|(_, brand)| brand)
+            .collect::<Vec<_>>();
+
+        if brands.iter().any(|brand| AVIF_BRANDS.contains(brand)) {
+            Some(Self::Avif)
+        } else if brands.iter().any(|brand| HEIC_BRANDS.contains(brand)) {
+            Some(Self::Heic)
+        } else {
+            None
+        }
+    }
+
+    /// Name of the ImageMagick coder used to write the format
+    pub fn magick_name(&self) -> &'static str {
+        match self {
+            Self::Png => "PNG",
+            Self::Jpeg => "JPEG",
+            Self::Gif => "GIF",
+            Self::WebP => "WEBP",
+            Self::Pnm => "PNM",
+            Self::Tiff => "TIFF",
+            Self::Tga => "TGA",
+            Self::Dds => "DDS",
+            Self::Bmp => "BMP",
+            Self::Ico => "ICO",
+            Self::Hdr => "HDR",
+            Self::OpenExr => "EXR",
+            Self::Farbfeld => "FARBFELD",
+            Self::Avif => "AVIF",
+            Self::Heic => "HEIC",
+            Self::Jxl => "JXL",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Avif => "image/avif",
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+            Self::Gif => "image/gif",
+            Self::WebP => "image/webp",
+            Self::Tiff => "image/tiff",
+            Self::Tga => "image/x-tga",
+            Self::Dds => "image/vnd-ms.dds",
+            Self::Bmp => "image/bmp",
+            Self::Ico => "image/x-icon",
+            Self::Hdr => "image/vnd.radiance",
+            Self::OpenExr => "image/x-exr",
+            Self::Pnm => "image/x-portable-bitmap",
+            Self::Heic => "image/heic",
+            Self::Jxl => "image/jxl",
+            Self::Farbfeld => "application/octet-stream",
+        }
+    }
+
+    /// Format used when an image is written without an explicit target format
+    ///
+    /// Formats browsers can not display (like HEIC photos from phones) are converted to JPEG
+    pub fn default_output(&self) -> Self {
+        match self {
+            Self::Heic => Self::Jpeg,
+            format => *format,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownFormat(pub String);
+
+impl Display for UnknownFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "Unknown Image Format: {}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_recognizes_supported_formats() {
+        assert_eq!(
+            Some(ImageFormat::Jpeg),
+            ImageFormat::sniff(b"\xff\xd8\xff\xe0\0\x10JFIF")
+        );
+        assert_eq!(
+            Some(ImageFormat::WebP),
+            ImageFormat::sniff(b"RIFF\x24\0\0\0WEBPVP8 ")
+        );
+        assert_eq!(
+            Some(ImageFormat::Tiff),
+            ImageFormat::sniff(b"II*\0\x08\0\0\0")
+        );
+        assert_eq!(
+            Some(ImageFormat::Pnm),
+            ImageFormat::sniff(b"P6\n640 480\n255\n")
+        );
+        assert_eq!(
+            Some(ImageFormat::Jxl),
+            ImageFormat::sniff(b"\xff\x0a\xfa\x7f")
+        );
+    }
+
+    #[test]
+    fn test_sniff_tells_avif_and_heic_apart_by_brand() {
+        assert_eq!(
+            Some(ImageFormat::Avif),
+            ImageFormat::sniff(b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf")
+        );
+        assert_eq!(
+            Some(ImageFormat::Avif),
+            ImageFormat::sniff(b"\0\0\0\x18ftypmif1\0\0\0\0mif1avif")
+        );
+        assert_eq!(
+            Some(ImageFormat::Heic),
+            ImageFormat::sniff(b"\0\0\0\x18ftypheic\0\0\0\0mif1heic")
+        );
+        assert_eq!(
+            None,
+            ImageFormat::sniff(b"\0\0\0\x14ftypisom\0\0\x02\0isom")
+        );
+    }
+
+    #[test]
+    fn test_sniff_rejects_formats_handled_by_dangerous_coders() {
+        assert_eq!(
+            None,
+            ImageFormat::sniff(b"<?xml version=\"1.0\"?><svg></svg>")
+        );
+        assert_eq!(None, ImageFormat::sniff(b"%!PS-Adobe-3.0"));
+        assert_eq!(
+            None,
+            ImageFormat::sniff(b"<?xml version=\"1.0\"?><image></image>")
+        );
+        assert_eq!(None, ImageFormat::sniff(b"text:/etc/passwd"));
+        assert_eq!(None, ImageFormat::sniff(b""));
+    }
+
+    #[test]
+    fn test_parse_list_rejects_unknown_formats() {
+        assert_eq!(
+            Ok(vec![ImageFormat::Avif, ImageFormat::WebP]),
+            ImageFormat::parse_list("avif, webp,").map_err(|err| err.0)
+        );
+        assert_eq!(
+            Err(String::from("svg")),
+            ImageFormat::parse_list("png, svg").map_err(|err| err.0)
+        );
+    }
+
+    #[test]
+    fn test_from_name_accepts_magick_names_and_extensions() {
+        assert_eq!(Some(ImageFormat::Heic), ImageFormat::from_name("HEIC"));
+        assert_eq!(Some(ImageFormat::Heic), ImageFormat::from_name("heif"));
+        assert_eq!(Some(ImageFormat::Jxl), ImageFormat::from_name(" jxl "));
+        assert_eq!(Some(ImageFormat::Tiff), ImageFormat::from_name("tif"));
+        assert_eq!(Some(ImageFormat::OpenExr), ImageFormat::from_name("EXR"));
+        assert_eq!(None, ImageFormat::from_name("svg"));
+        assert_eq!(None, ImageFormat::from_name(""));
+    }
+
+    #[test]
+    fn test_mime_type_matches_the_format() {
+        assert_eq!("image/heic", ImageFormat::Heic.mime_type());
+        assert_eq!("image/jxl", ImageFormat::Jxl.mime_type());
+        assert_eq!("image/avif", ImageFormat::Avif.mime_type());
+        assert_eq!("image/jpeg", ImageFormat::Jpeg.mime_type());
+    }
+
+    #[test]
+    fn test_default_output_converts_heic_to_jpeg() {
+        assert_eq!(ImageFormat::Jpeg, ImageFormat::Heic.default_output());
+        assert_eq!(ImageFormat::Jxl, ImageFormat::Jxl.default_output());
+        assert_eq!(ImageFormat::Png, ImageFormat::Png.default_output());
+    }
+}
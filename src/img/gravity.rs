@@ -0,0 +1,128 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Gravity {
+    #[default]
+    Center,
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+    /// content aware crop around the region of the image with the most detail
+    Smart,
+}
+
+impl Gravity {
+    /// Horizontal & vertical position of the gravity expressed as fractions of an image
+    fn position(&self) -> (f64, f64) {
+        match self {
+            Self::Center => (0.5, 0.5),
+            Self::North => (0.5, 0.0),
+            Self::South => (0.5, 1.0),
+            Self::East => (1.0, 0.5),
+            Self::West => (0.0, 0.5),
+            Self::NorthEast => (1.0, 0.0),
+            Self::NorthWest => (0.0, 0.0),
+            Self::SouthEast => (1.0, 1.0),
+            Self::SouthWest => (0.0, 1.0),
+            // smart gravity must be resolved into a focal point by the image itself
+            Self::Smart => (0.5, 0.5),
+        }
+    }
+}
+
+/// Decides which part of an image survives when it is cropped (or placed) into a different sized box
+#[derive(Clone, Copy, PartialEq)]
+pub enum Anchor {
+    Gravity(Gravity),
+    /// explicit focal point expressed as fractions of the source image
+    FocalPoint(f64, f64),
+}
+
+impl Default for Anchor {
+    fn default() -> Self {
+        Self::Gravity(Gravity::default())
+    }
+}
+
+impl Anchor {
+    /// Offsets of the smaller `inner` box positioned inside the larger `outer` box
+    pub fn offsets(&self, outer: (usize, usize), inner: (usize, usize)) -> (usize, usize) {
+        (
+            self.offset(outer.0, inner.0, |(x, _)| x),
+            self.offset(outer.1, inner.1, |(_, y)| y),
+        )
+    }
+
+    fn offset(&self, outer: usize, inner: usize, axis: fn((f64, f64)) -> f64) -> usize {
+        let excess = outer.saturating_sub(inner) as f64;
+
+        let offset = match self {
+            Self::Gravity(gravity) => excess * axis(gravity.position()),
+            // center the inner box on the focal point without leaving the outer box
+            Self::FocalPoint(x, y) => {
+                let focus = axis((*x, *y)).clamp(0.0, 1.0);
+                (outer as f64 * focus - inner as f64 / 2.0).clamp(0.0, excess)
+            }
+        };
+
+        offset.round() as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gravity_offsets() {
+        let outer = (400, 300);
+        let inner = (200, 100);
+
+        assert_eq!(
+            (100, 100),
+            Anchor::Gravity(Gravity::Center).offsets(outer, inner)
+        );
+        assert_eq!(
+            (100, 0),
+            Anchor::Gravity(Gravity::North).offsets(outer, inner)
+        );
+        assert_eq!(
+            (100, 200),
+            Anchor::Gravity(Gravity::South).offsets(outer, inner)
+        );
+        assert_eq!(
+            (200, 100),
+            Anchor::Gravity(Gravity::East).offsets(outer, inner)
+        );
+        assert_eq!(
+            (0, 100),
+            Anchor::Gravity(Gravity::West).offsets(outer, inner)
+        );
+        assert_eq!(
+            (0, 200),
+            Anchor::Gravity(Gravity::SouthWest).offsets(outer, inner)
+        );
+    }
+
+    #[test]
+    fn test_focal_point_offsets_are_kept_inside_the_outer_box() {
+        let outer = (400, 300);
+        let inner = (200, 100);
+
+        assert_eq!(
+            (50, 25),
+            Anchor::FocalPoint(0.375, 0.25).offsets(outer, inner)
+        );
+        assert_eq!((0, 0), Anchor::FocalPoint(0.0, 0.0).offsets(outer, inner));
+        assert_eq!(
+            (200, 200),
+            Anchor::FocalPoint(1.0, 1.0).offsets(outer, inner)
+        );
+    }
+}
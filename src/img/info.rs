@@ -0,0 +1,35 @@
+use magick_rust::bindings;
+use serde::Serialize;
+
+use super::ImageFormat;
+
+/// Intrinsic details about a source image
+#[derive(Serialize)]
+pub struct ImageInfo {
+    pub width: usize,
+    pub height: usize,
+    pub format: ImageFormat,
+    pub mime_type: &'static str,
+    pub frames: usize,
+    pub color_space: &'static str,
+    pub has_alpha: bool,
+    pub bytes: usize,
+    pub orientation: Option<u8>,
+}
+
+/// Human readable name of an ImageMagick colorspace
+pub fn color_space(colorspace: bindings::ColorspaceType) -> &'static str {
+    match colorspace {
+        bindings::ColorspaceType_sRGBColorspace => "srgb",
+        bindings::ColorspaceType_RGBColorspace => "rgb",
+        bindings::ColorspaceType_scRGBColorspace => "scrgb",
+        bindings::ColorspaceType_GRAYColorspace => "gray",
+        bindings::ColorspaceType_LinearGRAYColorspace => "linear-gray",
+        bindings::ColorspaceType_CMYColorspace => "cmy",
+        bindings::ColorspaceType_CMYKColorspace => "cmyk",
+        bindings::ColorspaceType_YCbCrColorspace => "ycbcr",
+        bindings::ColorspaceType_LabColorspace => "lab",
+        bindings::ColorspaceType_HSLColorspace => "hsl",
+        _ => "other",
+    }
+}
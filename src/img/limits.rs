@@ -0,0 +1,211 @@
+use magick_rust::{bindings, MagickWand};
+use std::os::raw::{c_char, c_void};
+use std::time::{Duration, Instant};
+
+use super::{ImageError, ImageFormat};
+
+/// Largest source image that will be decoded
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SourceLimits {
+    pub pixels: usize,
+    pub width: usize,
+    pub height: usize,
+    pub frames: usize,
+}
+
+impl SourceLimits {
+    /// Check the dimensions and frames declared in the image header without decoding any pixels
+    ///
+    /// A tiny compressed image can declare enormous dimensions (a decompression bomb) so
+    /// this has to happen before the image is read.
+    pub fn check(&self, bytes: &[u8], format: ImageFormat) -> Result<(), ImageError> {
+        let wand = MagickWand::new();
+        wand.set_format(format.magick_name())
+            .map_err(|_| ImageError::InvalidFormat)?;
+        wand.ping_image_blob(bytes)
+            .map_err(|_| ImageError::InvalidImage)?;
+
+        if wand.get_number_images() > self.frames {
+            return Err(ImageError::ImageTooLarge);
+        }
+
+        wand.reset_iterator();
+        while wand.next_image() {
+            let (width, height) = (wand.get_image_width(), wand.get_image_height());
+
+            if width > self.width
+                || height > self.height
+                || width.saturating_mul(height) > self.pixels
+            {
+                return Err(ImageError::ImageTooLarge);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Largest image that will be written
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct OutputLimits {
+    pub width: usize,
+    pub height: usize,
+    pub area: usize,
+    /// scale oversized outputs down to fit the limits instead of rejecting them
+    pub clamp: bool,
+}
+
+impl OutputLimits {
+    /// Factor (at most 1) that scales the output dimensions down to fit within the limits
+    pub fn scale(&self, width: usize, height: usize) -> Result<f64, ImageError> {
+        let exceeded = if width > self.width {
+            Some(Limit::Width)
+        } else if height > self.height {
+            Some(Limit::Height)
+        } else if width.saturating_mul(height) > self.area {
+            Some(Limit::Area)
+        } else {
+            None
+        };
+
+        match exceeded {
+            None => Ok(1.0),
+            Some(limit) if !self.clamp => Err(ImageError::OutputTooLarge(limit)),
+            Some(_) => Ok((self.width as f64 / width as f64)
+                .min(self.height as f64 / height as f64)
+                .min((self.area as f64 / (width as f64 * height as f64)).sqrt())
+                .min(1.0)),
+        }
+    }
+}
+
+/// Output limit exceeded by a request
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Limit {
+    Width,
+    Height,
+    Area,
+}
+
+/// Resources ImageMagick can use for a single image before giving up on it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ResourceLimits {
+    pub memory: u64,
+    pub map: u64,
+    pub disk: u64,
+    /// seconds spent on a single image (enforced with a `Deadline` on every image)
+    pub time: u64,
+}
+
+impl ResourceLimits {
+    /// Apply the memory, map & disk limits to every ImageMagick wand in the process
+    ///
+    /// The ImageMagick `time` resource is left alone since it limits the lifetime of the
+    /// whole process (and is fatal once exceeded) rather than the time spent on an image.
+    pub fn apply(&self) {
+        let limits = [
+            (bindings::ResourceType_MemoryResource, self.memory),
+            (bindings::ResourceType_MapResource, self.map),
+            (bindings::ResourceType_DiskResource, self.disk),
+        ];
+
+        for (resource, limit) in limits {
+            MagickWand::set_resource_limit(resource, limit)
+                .expect("Failed to set ImageMagick resource limit!");
+        }
+    }
+}
+
+/// Point in time after which ImageMagick aborts any operation on an image
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// Deadline `seconds` from now (boxed so its address stays stable while a wand watches it)
+    pub fn after(seconds: u64) -> Box<Self> {
+        Box::new(Self(Instant::now() + Duration::from_secs(seconds)))
+    }
+
+    pub fn has_passed(&self) -> bool {
+        Instant::now() >= self.0
+    }
+
+    /// Make every read, transform & write of the wand (and its clones) check the deadline
+    ///
+    /// The deadline must stay in place (e.g. boxed) and outlive the wand.
+    pub fn watch(&self, wand: &MagickWand) {
+        let deadline = self as *const Self as *mut c_void;
+
+        unsafe {
+            bindings::MagickSetProgressMonitor(wand.wand, Some(monitor), deadline);
+        }
+    }
+}
+
+/// Progress monitor that aborts the operation in progress once its deadline has passed
+unsafe extern "C" fn monitor(
+    _text: *const c_char,
+    _offset: bindings::MagickOffsetType,
+    _span: bindings::MagickSizeType,
+    deadline: *mut c_void,
+) -> bindings::MagickBooleanType {
+    let deadline = &*(deadline as *const Deadline);
+
+    if deadline.has_passed() {
+        bindings::MagickBooleanType_MagickFalse
+    } else {
+        bindings::MagickBooleanType_MagickTrue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: OutputLimits = OutputLimits {
+        width: 1000,
+        height: 500,
+        area: 250_000,
+        clamp: false,
+    };
+
+    #[test]
+    fn test_output_within_the_limits_is_not_scaled() {
+        assert!(matches!(LIMITS.scale(1000, 250), Ok(scale) if scale == 1.0));
+    }
+
+    #[test]
+    fn test_output_exceeding_the_limits_is_rejected_with_the_limit() {
+        assert!(matches!(
+            LIMITS.scale(2000, 100),
+            Err(ImageError::OutputTooLarge(Limit::Width))
+        ));
+        assert!(matches!(
+            LIMITS.scale(100, 600),
+            Err(ImageError::OutputTooLarge(Limit::Height))
+        ));
+        assert!(matches!(
+            LIMITS.scale(1000, 500),
+            Err(ImageError::OutputTooLarge(Limit::Area))
+        ));
+    }
+
+    #[test]
+    fn test_output_exceeding_the_limits_is_clamped() {
+        let limits = OutputLimits {
+            clamp: true,
+            ..LIMITS
+        };
+
+        assert!(matches!(limits.scale(2000, 100), Ok(scale) if scale == 0.5));
+        assert!(matches!(limits.scale(100, 1000), Ok(scale) if scale == 0.5));
+        assert!(
+            matches!(limits.scale(1000, 500), Ok(scale) if (scale - 0.5f64.sqrt()).abs() < 1e-9)
+        );
+    }
+
+    #[test]
+    fn test_deadline_passes_after_its_duration() {
+        assert!(Deadline::after(0).has_passed());
+        assert!(!Deadline::after(60).has_passed());
+    }
+}
@@ -1,7 +1,18 @@
+pub use self::crop::Crop;
 pub use self::error::ImageError;
-pub use self::format::ResizeImageFormat;
+pub use self::format::{ImageFormat, ResizeImageFormat};
+pub use self::gravity::{Anchor, Gravity};
+pub use self::info::ImageInfo;
+pub use self::limits::{Deadline, OutputLimits, ResourceLimits, SourceLimits};
+pub use self::mode::ResizeMode;
 pub use self::resizable::ResizableImage;
 
+pub mod crop;
 pub mod error;
 pub mod format;
+pub mod gravity;
+pub mod info;
+pub mod limits;
+pub mod mode;
 pub mod resizable;
+pub mod smart;
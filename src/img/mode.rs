@@ -0,0 +1,15 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeMode {
+    /// shrink (or grow) to fit inside the target box while preserving the aspect ratio
+    #[default]
+    Fit,
+    /// scale to cover the target box while preserving the aspect ratio then crop the overflow
+    Fill,
+    /// fit inside the target box and letterbox the remaining space with a background color
+    Pad,
+    /// scale to the exact target box ignoring the aspect ratio
+    Stretch,
+}
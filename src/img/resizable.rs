@@ -1,24 +1,166 @@
 use actix_web::web::Bytes;
-use image::ImageFormat;
-use magick_rust::MagickWand;
+use magick_rust::{bindings, MagickWand, PixelWand};
 use std::cmp;
 
-use super::ImageError;
+use super::{
+    info, smart, Anchor, Crop, Deadline, Gravity, ImageError, ImageFormat, ImageInfo, OutputLimits,
+    ResizeMode, SourceLimits,
+};
+
+/// Longest side of the sample analyzed to find a smart crop
+const SMART_SAMPLE_SIZE: usize = 128;
 
 pub struct ResizableImage {
     wand: MagickWand,
+    // declared after the wand so it is dropped after the wand watching it
+    deadline: Box<Deadline>,
 }
 
 impl ResizableImage {
-    pub fn from_bytes(bytes: &Bytes) -> Result<Self, ImageError> {
+    pub fn from_bytes(
+        bytes: &Bytes,
+        limits: &SourceLimits,
+        input_formats: &[ImageFormat],
+        deadline: Box<Deadline>,
+    ) -> Result<Self, ImageError> {
+        let format = ImageFormat::sniff(bytes)
+            .filter(|format| input_formats.contains(format))
+            .ok_or(ImageError::UnsupportedFormat)?;
+
+        limits.check(bytes, format)?;
+
+        // pin the coder so ImageMagick never picks one by sniffing the bytes itself
         let wand = MagickWand::new();
+        wand.set_format(format.magick_name())
+            .map_err(|_| ImageError::InvalidFormat)?;
+        deadline.watch(&wand);
+
         match wand.read_image_blob(bytes) {
-            Ok(_) => Ok(Self { wand }),
+            Ok(_) => Ok(Self { wand, deadline }),
+            Err(_) if deadline.has_passed() => Err(ImageError::TimedOut),
             Err(_) => Err(ImageError::InvalidImage),
         }
     }
 
-    pub fn resize(&self, width: Option<usize>, height: Option<usize>) {
+    pub fn crop(&mut self, crop: Crop) -> Result<(), ImageError> {
+        let (x, y, width, height) =
+            crop.region(self.wand.get_image_width(), self.wand.get_image_height())?;
+
+        let result = self.each_frame(|wand| {
+            wand.crop_image(width, height, x as isize, y as isize)
+                .and_then(|_| wand.reset_image_page(""))
+                .map_err(|_| ImageError::FailedWrite)
+        });
+        self.check_deadline(result)
+    }
+
+    /// Resize to dimensions already capped by `limit_output`
+    pub fn resize(
+        &mut self,
+        width: Option<usize>,
+        height: Option<usize>,
+        mode: ResizeMode,
+        anchor: Anchor,
+        background: &str,
+        enlarge: bool,
+    ) -> Result<(), ImageError> {
+        let result = match (mode, width, height) {
+            (ResizeMode::Fill, Some(width), Some(height)) => self.fill(width, height, anchor),
+            (ResizeMode::Pad, Some(width), Some(height)) => {
+                self.pad(width, height, anchor, background, enlarge)
+            }
+            (ResizeMode::Stretch, Some(width), Some(height)) => self.stretch(width, height),
+            // with a single dimension every mode preserves the aspect ratio
+            _ => {
+                self.fit(width, height);
+                Ok(())
+            }
+        };
+        self.check_deadline(result)
+    }
+
+    /// Scale the requested dimensions down until the output fits within the output limits
+    ///
+    /// Without `enlarge` the dimensions are capped to the original image first so a request the
+    /// cap brings within the limits is not rejected.
+    pub fn limit_output(
+        &self,
+        width: Option<usize>,
+        height: Option<usize>,
+        mode: ResizeMode,
+        enlarge: bool,
+        limits: &OutputLimits,
+    ) -> Result<(Option<usize>, Option<usize>), ImageError> {
+        let (width, height) = if enlarge {
+            (width, height)
+        } else {
+            self.limit(width, height, mode)
+        };
+
+        let (output_width, output_height) = match (mode, width, height) {
+            (ResizeMode::Fit, Some(width), Some(height)) => (
+                cmp::min(width, self.scale_width(height)),
+                cmp::min(height, self.scale_height(width)),
+            ),
+            (_, Some(width), Some(height)) => (width, height),
+            (_, Some(width), None) => (width, self.scale_height(width)),
+            (_, None, Some(height)) => (self.scale_width(height), height),
+            // without explicit dimensions the original dimensions are kept
+            (_, None, None) => return Ok((None, None)),
+        };
+
+        let scale = limits.scale(output_width, output_height)?;
+        let limit = |length: usize| cmp::max(1, (length as f64 * scale).floor() as usize);
+
+        Ok((width.map(limit), height.map(limit)))
+    }
+
+    /// Shrink the requested dimensions until the output fits inside the original image
+    ///
+    /// A fit is capped on the dimensions it would produce and a pad keeps its box (capping the
+    /// image inside it instead) while every other mode shrinks the requested box (preserving its
+    /// aspect ratio).
+    fn limit(
+        &self,
+        width: Option<usize>,
+        height: Option<usize>,
+        mode: ResizeMode,
+    ) -> (Option<usize>, Option<usize>) {
+        let (original_width, original_height) =
+            (self.wand.get_image_width(), self.wand.get_image_height());
+
+        match (mode, width, height) {
+            (ResizeMode::Fit, Some(width), Some(height)) => {
+                return if self.enlarges(width, height) {
+                    (Some(original_width), Some(original_height))
+                } else {
+                    (Some(width), Some(height))
+                };
+            }
+            (ResizeMode::Pad, Some(_), Some(_)) => return (width, height),
+            _ => (),
+        }
+
+        let factor = |target: Option<usize>, original: usize| {
+            target.map_or(1.0, |target| original as f64 / target as f64)
+        };
+        let factor = factor(width, original_width)
+            .min(factor(height, original_height))
+            .min(1.0);
+
+        (
+            width.map(|width| (width as f64 * factor).round() as usize),
+            height.map(|height| (height as f64 * factor).round() as usize),
+        )
+    }
+
+    /// Whether fitting inside the box would scale the image beyond its original dimensions
+    fn enlarges(&self, width: usize, height: usize) -> bool {
+        cmp::min(width, self.scale_width(height)) > self.wand.get_image_width()
+            || cmp::min(height, self.scale_height(width)) > self.wand.get_image_height()
+    }
+
+    fn fit(&self, width: Option<usize>, height: Option<usize>) {
         let (width, height) = match (width, height) {
             (Some(width), Some(height)) => (
                 cmp::min(width, self.scale_width(height)),
@@ -39,6 +181,121 @@ impl ResizableImage {
         }
     }
 
+    fn fill(&mut self, width: usize, height: usize, anchor: Anchor) -> Result<(), ImageError> {
+        // scale to cover the entire target box and then crop whatever overflows around the anchor
+        let scaled_width = cmp::max(width, self.scale_width(height));
+        let scaled_height = cmp::max(height, self.scale_height(width));
+
+        self.each_frame(|wand| {
+            wand.thumbnail_image(scaled_width, scaled_height);
+            Ok(())
+        })?;
+
+        // analyze the scaled image (instead of the full size source) to keep memory use down
+        let anchor = match anchor {
+            Anchor::Gravity(Gravity::Smart) => self.smart_anchor((width, height)),
+            anchor => anchor,
+        };
+        let (x, y) = anchor.offsets((scaled_width, scaled_height), (width, height));
+        let (x, y) = (x as isize, y as isize);
+
+        self.each_frame(|wand| {
+            wand.crop_image(width, height, x, y)
+                .and_then(|_| wand.reset_image_page(""))
+                .map_err(|_| ImageError::FailedWrite)
+        })
+    }
+
+    fn pad(
+        &mut self,
+        width: usize,
+        height: usize,
+        anchor: Anchor,
+        background: &str,
+        enlarge: bool,
+    ) -> Result<(), ImageError> {
+        let mut color = PixelWand::new();
+        color
+            .set_color(background)
+            .map_err(|_| ImageError::InvalidColor)?;
+
+        // fit inside the target box and then extend the canvas to the full target box
+        let (scaled_width, scaled_height) = if !enlarge && self.enlarges(width, height) {
+            (self.wand.get_image_width(), self.wand.get_image_height())
+        } else {
+            (
+                cmp::min(width, self.scale_width(height)),
+                cmp::min(height, self.scale_height(width)),
+            )
+        };
+        let (x, y) = anchor.offsets((width, height), (scaled_width, scaled_height));
+        let (x, y) = (-(x as isize), -(y as isize));
+
+        self.each_frame(|wand| {
+            wand.thumbnail_image(scaled_width, scaled_height);
+            wand.set_image_background_color(&color)
+                .and_then(|_| wand.extend_image(width, height, x, y))
+                .map_err(|_| ImageError::FailedWrite)
+        })
+    }
+
+    fn stretch(&mut self, width: usize, height: usize) -> Result<(), ImageError> {
+        if width == self.wand.get_image_width() && height == self.wand.get_image_height() {
+            return Ok(());
+        }
+
+        self.each_frame(|wand| {
+            wand.thumbnail_image(width, height);
+            Ok(())
+        })
+    }
+
+    fn smart_anchor(&self, window: (usize, usize)) -> Anchor {
+        let (width, height) = (self.wand.get_image_width(), self.wand.get_image_height());
+
+        // analyze a small grayscale sample to keep the scan cheap regardless of the image size
+        let ratio = SMART_SAMPLE_SIZE as f64 / cmp::max(width, height) as f64;
+        let sample = |length: usize| cmp::max(1, (length as f64 * ratio).round() as usize);
+        let (sample_width, sample_height) = (sample(width), sample(height));
+
+        // copy only the current frame (a clone of the wand would copy every frame)
+        let wand = unsafe { bindings::MagickGetImage(self.wand.wand) };
+        if wand.is_null() {
+            return Anchor::default();
+        }
+        let wand = MagickWand::new_from_wand(wand);
+        wand.thumbnail_image(sample_width, sample_height);
+
+        match wand.export_image_pixels(0, 0, sample_width, sample_height, "I") {
+            Some(luminance) => {
+                let (x, y) = smart::focal_point(
+                    &luminance,
+                    sample_width,
+                    sample_height,
+                    (sample(window.0), sample(window.1)),
+                );
+                Anchor::FocalPoint(x, y)
+            }
+            None => Anchor::default(),
+        }
+    }
+
+    fn each_frame<F>(&mut self, mut operation: F) -> Result<(), ImageError>
+    where
+        F: FnMut(&MagickWand) -> Result<(), ImageError>,
+    {
+        // flatten animation frames into full canvases so every frame is transformed consistently
+        if self.wand.get_image_scene() > 0 {
+            self.wand.coalesce().map_err(|_| ImageError::FailedWrite)?;
+        }
+
+        self.wand.reset_iterator();
+        while self.wand.next_image() {
+            operation(&self.wand)?;
+        }
+        Ok(())
+    }
+
     fn scale_width(&self, height: usize) -> usize {
         (self.wand.get_image_width() as f64 * (height as f64 / self.wand.get_image_height() as f64))
             as usize
@@ -66,36 +323,49 @@ impl ResizableImage {
             .set_image_compression_quality(quality as usize)
             .map_err(|_| ImageError::FailedWrite)?;
 
-        self.wand
-            .write_images_blob(format.extensions_str()[0])
-            .map_err(|_| ImageError::FailedWrite)
+        let result = self
+            .wand
+            .write_images_blob(format.magick_name())
+            .map_err(|_| ImageError::FailedWrite);
+        self.check_deadline(result)
+    }
+
+    /// Fail once the deadline has passed since ImageMagick aborts operations (some silently) then
+    fn check_deadline<T>(&self, result: Result<T, ImageError>) -> Result<T, ImageError> {
+        if self.deadline.has_passed() {
+            return Err(ImageError::TimedOut);
+        }
+        result
     }
 
     pub fn format(&self) -> Result<ImageFormat, ImageError> {
         self.wand
             .get_image_format()
             .map_err(|_| ImageError::InvalidFormat)
-            .and_then(|format| ImageFormat::from_extension(format).ok_or(ImageError::InvalidFormat))
+            .and_then(|format| ImageFormat::from_name(&format).ok_or(ImageError::InvalidFormat))
     }
 
     pub fn mime_type(self) -> Result<&'static str, ImageError> {
+        self.format().map(|format| format.mime_type())
+    }
+
+    pub fn info(&self, bytes: usize) -> Result<ImageInfo, ImageError> {
         let format = self.format()?;
 
-        match format {
-            ImageFormat::Avif => Ok("image/avif"),
-            ImageFormat::Jpeg => Ok("image/jpeg"),
-            ImageFormat::Png => Ok("image/png"),
-            ImageFormat::Gif => Ok("image/gif"),
-            ImageFormat::WebP => Ok("image/webp"),
-            ImageFormat::Tiff => Ok("image/tiff"),
-            ImageFormat::Tga => Ok("image/x-tga"),
-            ImageFormat::Dds => Ok("image/vnd-ms.dds"),
-            ImageFormat::Bmp => Ok("image/bmp"),
-            ImageFormat::Ico => Ok("image/x-icon"),
-            ImageFormat::Hdr => Ok("image/vnd.radiance"),
-            ImageFormat::OpenExr => Ok("image/x-exr"),
-            ImageFormat::Pnm => Ok("image/x-portable-bitmap"),
-            _ => Ok("application/octet-stream"),
-        }
+        Ok(ImageInfo {
+            width: self.wand.get_image_width(),
+            height: self.wand.get_image_height(),
+            format,
+            mime_type: format.mime_type(),
+            frames: self.wand.get_number_images(),
+            color_space: info::color_space(self.wand.get_image_colorspace()),
+            has_alpha: self.wand.get_image_alpha_channel(),
+            bytes,
+            orientation: self
+                .wand
+                .get_image_property("exif:Orientation")
+                .ok()
+                .and_then(|orientation| orientation.trim().parse::<u8>().ok()),
+        })
     }
 }
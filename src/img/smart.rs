@@ -0,0 +1,91 @@
+/// Find the most "interesting" crop window in a grayscale image
+///
+/// Every pixel is scored by its edge energy (the absolute luminance gradient)
+/// and the `window` sized region with the highest total energy wins.
+/// Returns the center of the winning window expressed as fractions of the image
+/// so the result can be used as a focal point at any scale.
+pub fn focal_point(
+    luminance: &[u8],
+    width: usize,
+    height: usize,
+    window: (usize, usize),
+) -> (f64, f64) {
+    if width < 3 || height < 3 || luminance.len() < width * height {
+        return (0.5, 0.5);
+    }
+
+    let (window_width, window_height) = (window.0.clamp(1, width), window.1.clamp(1, height));
+
+    // summed area table of the edge energy (padded with a leading row & column of zeroes)
+    let mut table = vec![0u64; (width + 1) * (height + 1)];
+    for y in 0..height {
+        let mut row = 0u64;
+        for x in 0..width {
+            row += energy(luminance, width, height, x, y);
+            table[(y + 1) * (width + 1) + x + 1] = table[y * (width + 1) + x + 1] + row;
+        }
+    }
+
+    let sum = |x: usize, y: usize| {
+        let (right, bottom) = (x + window_width, y + window_height);
+        table[bottom * (width + 1) + right] + table[y * (width + 1) + x]
+            - table[y * (width + 1) + right]
+            - table[bottom * (width + 1) + x]
+    };
+
+    // start from the centered window so featureless images are not pulled into a corner
+    let mut best = ((width - window_width) / 2, (height - window_height) / 2);
+    let mut best_energy = sum(best.0, best.1);
+
+    for y in 0..=(height - window_height) {
+        for x in 0..=(width - window_width) {
+            let energy = sum(x, y);
+            if energy > best_energy {
+                best = (x, y);
+                best_energy = energy;
+            }
+        }
+    }
+
+    (
+        (best.0 as f64 + window_width as f64 / 2.0) / width as f64,
+        (best.1 as f64 + window_height as f64 / 2.0) / height as f64,
+    )
+}
+
+fn energy(luminance: &[u8], width: usize, height: usize, x: usize, y: usize) -> u64 {
+    let at = |x: usize, y: usize| luminance[y * width + x] as i64;
+
+    let horizontal = at((x + 1).min(width - 1), y) - at(x.saturating_sub(1), y);
+    let vertical = at(x, (y + 1).min(height - 1)) - at(x, y.saturating_sub(1));
+
+    (horizontal.abs() + vertical.abs()) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_focal_point_of_a_featureless_image_is_the_center() {
+        let luminance = vec![128; 10 * 10];
+
+        assert_eq!((0.5, 0.5), focal_point(&luminance, 10, 10, (4, 4)));
+    }
+
+    #[test]
+    fn test_focal_point_follows_the_edges() {
+        // flat image with a single bright square in the bottom right corner
+        let mut luminance = vec![0; 10 * 10];
+        for y in 7..9 {
+            for x in 7..9 {
+                luminance[y * 10 + x] = 255;
+            }
+        }
+
+        let (x, y) = focal_point(&luminance, 10, 10, (4, 4));
+
+        assert!(x > 0.5, "focal point moves right");
+        assert!(y > 0.5, "focal point moves down");
+    }
+}
@@ -1,54 +1,178 @@
 extern crate log;
 
-use actix_http::header;
+use actix_http::{header, StatusCode};
 use actix_web::dev::Server;
 use actix_web::web::Data;
 use actix_web::HttpRequest;
 use actix_web::{error, middleware::Logger, web, App, HttpResponse, HttpServer, Responder};
 use cadence::StatsdClient;
+use http::allowlist::{HostList, InvalidHostPattern};
+use http::middleware::errors::{error_response, ErrorCode, ErrorFormat};
 use http::middleware::statsd::StatsD;
-use http::Client;
-use image::ImageFormat;
-use img::{ImageError, ResizableImage, ResizeImageFormat};
+use http::query::{self, QueryError};
+use http::resolver::{AddressList, DEFAULT_BLOCKED_ADDRESSES};
+use http::{accept, hints, signature, Client, ClientHints};
+use img::format::UnknownFormat;
+use img::limits::Limit;
+use img::{
+    Anchor, Crop, Deadline, Gravity, ImageError, ResizableImage, ResizeImageFormat, ResizeMode,
+};
 use magick_rust::magick_wand_genesis;
 use rand::Rng;
 use serde::Deserialize;
-use std::collections::HashSet;
+use std::cmp;
 use std::net::TcpListener;
 use std::sync::{Arc, Once};
 use std::time::{Duration, SystemTime};
-use url::Host;
 
 mod http;
 mod img;
 
+pub use http::client::{ConnectionPool, Retries, Timeouts};
+pub use img::{ImageFormat, OutputLimits, ResourceLimits, SourceLimits};
+
 static START: Once = Once::new();
-const ACCEPTS_WEBP_HEADER: &[u8; 10] = b"image/webp";
+const DEFAULT_BACKGROUND: &str = "white";
+const DEFAULT_MAX_DPR: f32 = 3.0;
+const DEFAULT_SAVE_DATA_QUALITY: u8 = 65;
+const MAX_DIMENSION: f64 = 100_000.0;
+const MAX_DPR_PARAMETER: f64 = 100.0;
+const RESIZE_PARAMETERS: [&str; 15] = [
+    "source",
+    "height",
+    "width",
+    "quality",
+    "format",
+    "mode",
+    "background",
+    "gravity",
+    "fx",
+    "fy",
+    "crop",
+    "enlarge",
+    "dpr",
+    "expires",
+    "signature",
+];
+const INFO_PARAMETERS: [&str; 1] = ["source"];
+const DEFAULT_AUTO_FORMATS: [ImageFormat; 1] = [ImageFormat::WebP];
+const DEFAULT_INPUT_FORMATS: [ImageFormat; 15] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Gif,
+    ImageFormat::WebP,
+    ImageFormat::Pnm,
+    ImageFormat::Tiff,
+    ImageFormat::Tga,
+    ImageFormat::Dds,
+    ImageFormat::Bmp,
+    ImageFormat::Ico,
+    ImageFormat::Hdr,
+    ImageFormat::OpenExr,
+    ImageFormat::Farbfeld,
+    ImageFormat::Avif,
+    ImageFormat::Heic,
+];
+const DEFAULT_MAX_REDIRECTS: u8 = 3;
+const DEFAULT_MAX_SOURCE_BYTES: usize = 20_000_000;
+const DEFAULT_CONNECTION_POOL: ConnectionPool = ConnectionPool {
+    size: 100,
+    idle_timeout: 15,
+    per_origin: 0,
+};
+const DEFAULT_TIMEOUTS: Timeouts = Timeouts {
+    connect: 5,
+    headers: 5,
+    body: 30,
+};
+const DEFAULT_RETRIES: Retries = Retries {
+    attempts: 2,
+    backoff: 100,
+};
+const DEFAULT_SOURCE_LIMITS: SourceLimits = SourceLimits {
+    pixels: 100_000_000,
+    width: 20_000,
+    height: 20_000,
+    frames: 1_000,
+};
+const DEFAULT_OUTPUT_LIMITS: OutputLimits = OutputLimits {
+    width: 8_192,
+    height: 8_192,
+    area: 50_000_000,
+    clamp: false,
+};
+const DEFAULT_RESOURCE_LIMITS: ResourceLimits = ResourceLimits {
+    memory: 1 << 30,
+    map: 2 << 30,
+    disk: 4 << 30,
+    time: 60,
+};
 
 #[derive(Clone)]
 pub struct Configuration {
     pub env: String,
-    pub allowed_hosts: HashSet<Host>,
+    pub allowed_hosts: HostList,
+    pub denied_hosts: HostList,
+    pub blocked_addresses: AddressList,
+    pub max_redirects: u8,
+    pub max_source_bytes: usize,
+    pub connection_pool: ConnectionPool,
+    pub timeouts: Timeouts,
+    pub retries: Retries,
+    pub source_limits: SourceLimits,
+    pub output_limits: OutputLimits,
+    pub resource_limits: ResourceLimits,
     pub cache_expiration: u64,
     pub cache_jitter: u64,
     pub default_quality: u8,
+    pub enlarge: bool,
+    pub max_dpr: f32,
+    pub high_dpr_quality: Option<u8>,
+    pub client_hints: bool,
+    pub save_data_quality: u8,
+    pub auto_formats: Vec<ImageFormat>,
+    pub input_formats: Vec<ImageFormat>,
+    pub signing_keys: Vec<String>,
+    pub strict_query: bool,
 }
 
 impl Configuration {
     /// Create a new Configuration with default values and correctly transformed options
     ///
     /// ```rust
-    /// # use url::Host;
-    /// # use std::collections::HashSet;
-    /// # use rusty_resizer::Configuration;
+    /// # use url::Url;
+    /// # use rusty_resizer::{Configuration, ImageFormat};
     ///
-    /// let config = Configuration::new(String::from("test"), String::from("  x.com,  y.com,z.com"), 2880, 60, 50);
+    /// let config = Configuration::new(String::from("test"), String::from("  x.com,  y.com,*.z.com"), 2880, 60, 50).unwrap();
     ///
     /// assert_eq!("test", config.env);
-    /// assert_eq!(HashSet::from_iter(vec![Host::parse("x.com").unwrap(), Host::parse("y.com").unwrap(), Host::parse("z.com").unwrap()]), config.allowed_hosts);
+    /// assert!(config.allowed_hosts.matches(&Url::parse("https://x.com/a.jpg").unwrap()));
+    /// assert!(config.allowed_hosts.matches(&Url::parse("https://y.com/a.jpg").unwrap()));
+    /// assert!(config.allowed_hosts.matches(&Url::parse("https://cdn.z.com/a.jpg").unwrap()));
+    /// assert!(!config.allowed_hosts.matches(&Url::parse("https://z.com/a.jpg").unwrap()));
+    /// assert!(config.denied_hosts.is_empty());
+    /// assert!(config.blocked_addresses.contains(&"169.254.169.254".parse().unwrap()));
+    /// assert_eq!(3, config.max_redirects);
+    /// assert_eq!(20_000_000, config.max_source_bytes);
+    /// assert_eq!(100, config.connection_pool.size);
+    /// assert_eq!(5, config.timeouts.connect);
+    /// assert_eq!(2, config.retries.attempts);
+    /// assert_eq!(100_000_000, config.source_limits.pixels);
+    /// assert_eq!(8_192, config.output_limits.width);
+    /// assert!(!config.output_limits.clamp);
+    /// assert_eq!(60, config.resource_limits.time);
     /// assert_eq!(2880, config.cache_expiration);
     /// assert_eq!(60, config.cache_jitter);
     /// assert_eq!(50, config.default_quality);
+    /// assert!(config.enlarge);
+    /// assert_eq!(3.0, config.max_dpr);
+    /// assert_eq!(None, config.high_dpr_quality);
+    /// assert!(!config.client_hints);
+    /// assert_eq!(65, config.save_data_quality);
+    /// assert_eq!(vec![ImageFormat::WebP], config.auto_formats);
+    /// assert_eq!(15, config.input_formats.len());
+    /// assert!(config.signing_keys.is_empty());
+    /// assert!(!config.strict_query);
     /// ```
     pub fn new(
         env: String,
@@ -56,95 +180,482 @@ impl Configuration {
         cache_expiration: u64,
         cache_jitter: u64,
         default_quality: u8,
-    ) -> Self {
-        let allowed_hosts = allowed_hosts
-            .chars()
-            .filter(|c| !c.is_whitespace())
-            .collect::<String>()
-            .split(',')
-            .map(str::to_string)
-            .filter_map(|s| Host::parse(&s).ok())
-            .collect::<HashSet<Host>>();
+    ) -> Result<Self, InvalidHostPattern> {
+        let allowed_hosts = HostList::parse(&allowed_hosts)?;
 
-        Configuration {
+        Ok(Configuration {
             env,
             allowed_hosts,
+            denied_hosts: HostList::default(),
+            blocked_addresses: AddressList::parse(DEFAULT_BLOCKED_ADDRESSES)?,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            max_source_bytes: DEFAULT_MAX_SOURCE_BYTES,
+            connection_pool: DEFAULT_CONNECTION_POOL,
+            timeouts: DEFAULT_TIMEOUTS,
+            retries: DEFAULT_RETRIES,
+            source_limits: DEFAULT_SOURCE_LIMITS,
+            output_limits: DEFAULT_OUTPUT_LIMITS,
+            resource_limits: DEFAULT_RESOURCE_LIMITS,
             cache_expiration,
             cache_jitter,
             default_quality,
-        }
+            enlarge: true,
+            max_dpr: DEFAULT_MAX_DPR,
+            high_dpr_quality: None,
+            client_hints: false,
+            save_data_quality: DEFAULT_SAVE_DATA_QUALITY,
+            auto_formats: DEFAULT_AUTO_FORMATS.to_vec(),
+            input_formats: DEFAULT_INPUT_FORMATS.to_vec(),
+            signing_keys: vec![],
+            strict_query: false,
+        })
+    }
+
+    /// Hosts that images can never be fetched from even when they match the `allowed_hosts`
+    ///
+    /// ```rust
+    /// # use url::Url;
+    /// # use rusty_resizer::Configuration;
+    ///
+    /// let config = Configuration::new(String::from("test"), String::from(".x.com"), 2880, 60, 50)
+    ///     .and_then(|config| config.with_denied_hosts(String::from("private.x.com, 10.0.0.0/8")))
+    ///     .unwrap();
+    ///
+    /// assert!(config.denied_hosts.matches(&Url::parse("https://private.x.com/a.jpg").unwrap()));
+    /// assert!(config.denied_hosts.matches(&Url::parse("https://10.1.2.3/a.jpg").unwrap()));
+    /// assert!(Configuration::new(String::from("test"), String::from("x.com, *."), 2880, 60, 50).is_err());
+    /// ```
+    pub fn with_denied_hosts(mut self, denied_hosts: String) -> Result<Self, InvalidHostPattern> {
+        self.denied_hosts = HostList::parse(&denied_hosts)?;
+        Ok(self)
+    }
+
+    /// IP addresses and ranges that image hosts can never resolve to (replacing the default
+    /// private, loopback and link-local ranges)
+    ///
+    /// ```rust
+    /// # use rusty_resizer::Configuration;
+    ///
+    /// let config = Configuration::new(String::from("test"), String::from("x.com"), 2880, 60, 50)
+    ///     .and_then(|config| config.with_blocked_addresses(String::from("10.0.0.0/8, fd00::/8")))
+    ///     .unwrap();
+    ///
+    /// assert!(config.blocked_addresses.contains(&"10.1.2.3".parse().unwrap()));
+    /// assert!(!config.blocked_addresses.contains(&"127.0.0.1".parse().unwrap()));
+    /// assert!(Configuration::new(String::from("test"), String::from("x.com"), 2880, 60, 50)
+    ///     .and_then(|config| config.with_blocked_addresses(String::from("x.com")))
+    ///     .is_err());
+    /// ```
+    pub fn with_blocked_addresses(
+        mut self,
+        blocked_addresses: String,
+    ) -> Result<Self, InvalidHostPattern> {
+        self.blocked_addresses = AddressList::parse(&blocked_addresses)?;
+        Ok(self)
+    }
+
+    /// Maximum number of upstream redirects followed (`0` disables following redirects)
+    pub fn with_max_redirects(mut self, max_redirects: u8) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Largest source image download (in bytes) that will be accepted
+    pub fn with_max_source_bytes(mut self, max_source_bytes: usize) -> Self {
+        self.max_source_bytes = max_source_bytes;
+        self
+    }
+
+    /// Size, idle timeout and per origin limit of the pooled upstream connections
+    pub fn with_connection_pool(mut self, connection_pool: ConnectionPool) -> Self {
+        self.connection_pool = connection_pool;
+        self
+    }
+
+    /// Connect, response headers and response body timeouts (in seconds) for upstream requests
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Number of retries (and the backoff between them) for transient upstream failures
+    pub fn with_retries(mut self, retries: Retries) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Largest source image (pixels, width, height & frames) that will be decoded
+    pub fn with_source_limits(mut self, source_limits: SourceLimits) -> Self {
+        self.source_limits = source_limits;
+        self
+    }
+
+    /// Largest image (width, height & area) that will be written and whether larger requests
+    /// are clamped to fit or rejected
+    pub fn with_output_limits(mut self, output_limits: OutputLimits) -> Self {
+        self.output_limits = output_limits;
+        self
+    }
+
+    /// Memory, map & disk (in bytes) and time (in seconds) ImageMagick can spend on an image
+    pub fn with_resource_limits(mut self, resource_limits: ResourceLimits) -> Self {
+        self.resource_limits = resource_limits;
+        self
+    }
+
+    /// Allow (or prevent) images from being resized beyond their original dimensions
+    pub fn with_enlarge(mut self, enlarge: bool) -> Self {
+        self.enlarge = enlarge;
+        self
+    }
+
+    /// Cap the device pixel ratio multiplier a request can ask for
+    pub fn with_max_dpr(mut self, max_dpr: f32) -> Self {
+        self.max_dpr = max_dpr;
+        self
+    }
+
+    /// Default quality used instead of `default_quality` for device pixel ratios above 1
+    pub fn with_high_dpr_quality(mut self, high_dpr_quality: Option<u8>) -> Self {
+        self.high_dpr_quality = high_dpr_quality;
+        self
+    }
+
+    /// Size images with Client Hints request headers when explicit query parameters are absent
+    ///
+    /// Client Hints are ignored when signing keys are configured since they are unsigned headers.
+    pub fn with_client_hints(mut self, client_hints: bool) -> Self {
+        self.client_hints = client_hints;
+        self
+    }
+
+    /// Whether Client Hints can size images (never for signed requests so the signature alone
+    /// determines the output)
+    fn uses_client_hints(&self) -> bool {
+        self.client_hints && self.signing_keys.is_empty()
+    }
+
+    /// Maximum default quality used for requests with a `Save-Data: on` Client Hint
+    pub fn with_save_data_quality(mut self, save_data_quality: u8) -> Self {
+        self.save_data_quality = save_data_quality;
+        self
+    }
+
+    /// Formats (in order of preference) that `format=auto` can convert images into
+    ///
+    /// ```rust
+    /// # use rusty_resizer::{Configuration, ImageFormat};
+    ///
+    /// let config = Configuration::new(String::from("test"), String::from("x.com"), 2880, 60, 50)
+    ///     .unwrap()
+    ///     .with_auto_formats(String::from("avif, webp"))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(vec![ImageFormat::Avif, ImageFormat::WebP], config.auto_formats);
+    /// assert!(config.with_auto_formats(String::from("avif, unknown")).is_err());
+    /// ```
+    pub fn with_auto_formats(mut self, auto_formats: String) -> Result<Self, UnknownFormat> {
+        self.auto_formats = ImageFormat::parse_list(&auto_formats)?;
+        Ok(self)
+    }
+
+    /// Formats source images can be in (anything else is rejected before reaching ImageMagick)
+    ///
+    /// ```rust
+    /// # use rusty_resizer::{Configuration, ImageFormat};
+    ///
+    /// let config = Configuration::new(String::from("test"), String::from("x.com"), 2880, 60, 50)
+    ///     .unwrap()
+    ///     .with_input_formats(String::from("jpeg, png"))
+    ///     .unwrap();
+    ///
+    /// assert_eq!(vec![ImageFormat::Jpeg, ImageFormat::Png], config.input_formats);
+    /// assert!(config.with_input_formats(String::from("jpeg, svg")).is_err());
+    /// ```
+    pub fn with_input_formats(mut self, input_formats: String) -> Result<Self, UnknownFormat> {
+        self.input_formats = ImageFormat::parse_list(&input_formats)?;
+        Ok(self)
+    }
+
+    /// Require every resize request to be signed with one of these comma separated keys
+    ///
+    /// ```rust
+    /// # use rusty_resizer::Configuration;
+    ///
+    /// let config = Configuration::new(String::from("test"), String::from("x.com"), 2880, 60, 50)
+    ///     .unwrap()
+    ///     .with_signing_keys(String::from("current, previous,"));
+    ///
+    /// assert_eq!(vec![String::from("current"), String::from("previous")], config.signing_keys);
+    /// ```
+    pub fn with_signing_keys(mut self, signing_keys: String) -> Self {
+        self.signing_keys = signing_keys
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect();
+        self
+    }
+
+    /// Reject requests with query parameters the endpoint does not accept (e.g. typos)
+    pub fn with_strict_query(mut self, strict_query: bool) -> Self {
+        self.strict_query = strict_query;
+        self
     }
 }
 
-fn supports_webp(request: &HttpRequest) -> bool {
-    match request.headers().get(header::ACCEPT) {
-        Some(accept_header) => accept_header
-            .as_bytes()
-            .windows(ACCEPTS_WEBP_HEADER.len())
-            .any(move |sub_slice| sub_slice == ACCEPTS_WEBP_HEADER),
-        None => false,
+/// `deserialize_with` functions that name their parameter when its value can not be parsed
+mod parameters {
+    use serde::{Deserialize, Deserializer};
+
+    use crate::http::query;
+
+    macro_rules! named {
+        ($($parameter:ident),*) => {
+            $(
+                pub fn $parameter<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+                where
+                    D: Deserializer<'de>,
+                    T: Deserialize<'de>,
+                {
+                    query::named(stringify!($parameter), deserializer)
+                }
+            )*
+        };
     }
+
+    named!(height, width, quality, format, mode, gravity, fx, fy, crop, enlarge, dpr, expires);
 }
 
 #[derive(Deserialize)]
 struct ResizeOptions {
     source: String,
+    #[serde(default, deserialize_with = "parameters::height")]
     height: Option<f32>,
+    #[serde(default, deserialize_with = "parameters::width")]
     width: Option<f32>,
-    quality: Option<u8>,
+    // wider than a quality so out of range values are reported by `validate`
+    #[serde(default, deserialize_with = "parameters::quality")]
+    quality: Option<u32>,
+    #[serde(default, deserialize_with = "parameters::format")]
     format: Option<ResizeImageFormat>,
+    #[serde(default, deserialize_with = "parameters::mode")]
+    mode: Option<ResizeMode>,
+    background: Option<String>,
+    #[serde(default, deserialize_with = "parameters::gravity")]
+    gravity: Option<Gravity>,
+    #[serde(default, deserialize_with = "parameters::fx")]
+    fx: Option<f64>,
+    #[serde(default, deserialize_with = "parameters::fy")]
+    fy: Option<f64>,
+    #[serde(default, deserialize_with = "parameters::crop")]
+    crop: Option<Crop>,
+    #[serde(default, deserialize_with = "parameters::enlarge")]
+    enlarge: Option<bool>,
+    #[serde(default, deserialize_with = "parameters::dpr")]
+    dpr: Option<f32>,
+    #[serde(default, deserialize_with = "parameters::expires")]
+    expires: Option<u64>,
+}
+
+impl ResizeOptions {
+    /// Reject numbers outside of the ranges a resize can honour
+    fn validate(&self) -> Result<(), QueryError> {
+        query::check_range("width", self.width, 1.0, MAX_DIMENSION)?;
+        query::check_range("height", self.height, 1.0, MAX_DIMENSION)?;
+        query::check_range("quality", self.quality, 1.0, 100.0)?;
+        query::check_range("fx", self.fx, 0.0, 1.0)?;
+        query::check_range("fy", self.fy, 0.0, 1.0)?;
+        query::check_range("dpr", self.dpr, 0.1, MAX_DPR_PARAMETER)
+    }
+
+    /// An explicit focal point takes precedence over the gravity
+    fn anchor(&self) -> Anchor {
+        match (self.fx, self.fy) {
+            (None, None) => Anchor::Gravity(self.gravity.unwrap_or_default()),
+            (fx, fy) => Anchor::FocalPoint(fx.unwrap_or(0.5), fy.unwrap_or(0.5)),
+        }
+    }
+
+    /// Device pixel ratio multiplier capped at the configured maximum
+    fn dpr(&self, hints: &ClientHints, max_dpr: f32) -> f32 {
+        self.dpr
+            .or(hints.dpr)
+            .filter(|dpr| *dpr > 0.0)
+            .unwrap_or(1.0)
+            .min(max_dpr)
+    }
+
+    /// Target width in physical pixels falling back to the Client Hints
+    fn width(&self, hints: &ClientHints, dpr: f32) -> Option<f32> {
+        self.width
+            .map(|width| width * dpr)
+            .or(hints.width)
+            .or(hints.viewport_width.map(|width| width * dpr))
+    }
+
+    /// Target height in physical pixels
+    fn height(&self, dpr: f32) -> Option<f32> {
+        self.height.map(|height| height * dpr)
+    }
+
+    /// An explicit quality takes precedence over the configured defaults
+    fn quality(&self, configuration: &Configuration, hints: &ClientHints, dpr: f32) -> u8 {
+        let default_quality = match configuration.high_dpr_quality {
+            Some(high_dpr_quality) if dpr > 1.0 => high_dpr_quality,
+            _ => configuration.default_quality,
+        };
+
+        match self.quality {
+            // validated to be within 1..=100
+            Some(quality) => quality as u8,
+            None if hints.save_data => cmp::min(default_quality, configuration.save_data_quality),
+            None => default_quality,
+        }
+    }
+
+    /// Client Hints that could have changed the response when they are enabled
+    fn vary(&self) -> Vec<&'static str> {
+        let mut vary = vec![];
+        if self.dpr.is_none() {
+            vary.push(hints::SEC_CH_DPR);
+        }
+        if self.width.is_none() {
+            vary.push(hints::SEC_CH_WIDTH);
+            vary.push(hints::SEC_CH_VIEWPORT_WIDTH);
+        }
+        if self.quality.is_none() {
+            vary.push(hints::SAVE_DATA);
+        }
+        vary
+    }
 }
 
 /// Resize an image
 ///
-/// Accepts five query parameters:
+/// Accepts fourteen query parameters:
 ///     - source
 ///     - height
 ///     - width
 ///     - quality
 ///     - format
+///     - mode
+///     - background
+///     - gravity
+///     - fx
+///     - fy
+///     - crop
+///     - enlarge
+///     - dpr
+///     - expires
 ///
 /// Example request:
-///  resize?source=url.jpeg&height=500&width=500&max_quality=85&format=webp
+///  resize?source=url.jpeg&height=500&width=500&quality=85&format=webp&mode=fill&gravity=north
+///
+/// When signing keys are configured every request must also include a valid `signature`
+/// and requests past their `expires` Unix timestamp are gone
+///
+/// Out of range numbers (and unknown parameters in strict mode) are rejected up front
 ///
 async fn resize(
     options: web::Query<ResizeOptions>,
     configuration: web::Data<Configuration>,
+    client: web::Data<Client>,
     request: HttpRequest,
 ) -> Result<HttpResponse, ImageError> {
-    let client = Client::new(&configuration.allowed_hosts);
+    let validation = if configuration.strict_query {
+        query::check_known(request.query_string(), &RESIZE_PARAMETERS)
+    } else {
+        Ok(())
+    };
+
+    if let Err(err) = validation.and_then(|_| options.validate()) {
+        return Ok(error_response(StatusCode::BAD_REQUEST, &err));
+    }
+
+    if !configuration.signing_keys.is_empty() {
+        if let Err(err) = signature::verify(request.query_string(), &configuration.signing_keys) {
+            return Ok(error_response(StatusCode::FORBIDDEN, &err));
+        }
+    }
+
+    let now = SystemTime::now();
+
+    let lifetime = options
+        .expires
+        .map(|expires| signature::remaining(expires, now))
+        .transpose();
+
+    let lifetime = match lifetime {
+        Ok(lifetime) => lifetime,
+        Err(err) => return Ok(error_response(StatusCode::GONE, &err)),
+    };
 
     let response = client.get(&options.source).await;
 
     match response {
         Ok(response) => {
-            let mut image = ResizableImage::from_bytes(&response)?;
+            let mut image = ResizableImage::from_bytes(
+                &response,
+                &configuration.source_limits,
+                &configuration.input_formats,
+                Deadline::after(configuration.resource_limits.time),
+            )?;
+
+            if let Some(crop) = options.crop {
+                image.crop(crop)?;
+            }
+
+            let hints = if configuration.uses_client_hints() {
+                ClientHints::from_request(&request)
+            } else {
+                ClientHints::default()
+            };
+            let dpr = options.dpr(&hints, configuration.max_dpr);
+            let mode = options.mode.unwrap_or_default();
+            let enlarge = options.enlarge.unwrap_or(configuration.enlarge);
+
+            let (width, height) = image.limit_output(
+                options.width(&hints, dpr).map(|f| f.round() as usize),
+                options.height(dpr).map(|f| f.round() as usize),
+                mode,
+                enlarge,
+                &configuration.output_limits,
+            )?;
 
             image.resize(
-                options.width.map(|f| f.round() as usize),
-                options.height.map(|f| f.round() as usize),
-            );
+                width,
+                height,
+                mode,
+                options.anchor(),
+                options.background.as_deref().unwrap_or(DEFAULT_BACKGROUND),
+                enlarge,
+            )?;
 
             let format = options.format.and_then(|request_format| {
                 // If automatic content negotiation is enabled
-                // attempt to convert to WebP when that is supported by incoming request
-                if request_format == ResizeImageFormat::Auto && supports_webp(&request) {
-                    Some(ImageFormat::WebP)
+                // attempt to convert to the most preferred format supported by incoming request
+                if request_format == ResizeImageFormat::Auto {
+                    accept::preferred_format(&request, &configuration.auto_formats)
                 } else {
                     request_format.into()
                 }
             });
 
             let buffer = image.to_buffer_mut(
-                options.quality.unwrap_or(configuration.default_quality),
-                format.unwrap_or(image.format()?),
+                options.quality(&configuration, &hints, dpr),
+                format.unwrap_or(image.format()?.default_output()),
             )?;
 
             let content_type = image.mime_type()?;
 
-            let now = SystemTime::now();
             let jitter = rand::thread_rng().gen_range(0..=configuration.cache_jitter);
             let expire_time_in_seconds = configuration.cache_expiration * 60 * 60 + jitter;
+            // never let a CDN cache an expiring URL beyond its lifetime
+            let expire_time_in_seconds = lifetime.map_or(expire_time_in_seconds, |lifetime| {
+                cmp::min(expire_time_in_seconds, lifetime.as_secs())
+            });
 
             let mut builder = HttpResponse::Ok();
 
@@ -160,21 +671,121 @@ async fn resize(
                     httpdate::fmt_http_date(now + Duration::from_secs(expire_time_in_seconds)),
                 ));
 
+            let mut vary = vec![];
+
             if options.format == Some(ResizeImageFormat::Auto) {
-                builder.insert_header((header::VARY, "Accept"));
+                vary.push("Accept");
+            }
+
+            if configuration.uses_client_hints() {
+                builder.insert_header((hints::ACCEPT_CH, hints::ACCEPTED_HINTS));
+                vary.append(&mut options.vary());
+            }
+
+            if !vary.is_empty() {
+                builder.insert_header((header::VARY, vary.join(", ")));
             }
 
             let response = builder.body(buffer);
 
             Ok(response)
         }
-        Err(err) => Ok(HttpResponse::BadRequest().body(err.to_string())),
+        Err(err) => Ok(error_response(err.status_code(), &err)),
+    }
+}
+
+#[derive(Deserialize)]
+struct InfoOptions {
+    source: String,
+}
+
+/// Inspect an image
+///
+/// Accepts one query parameter:
+///     - source
+///
+/// Example request:
+///  info?source=url.jpeg
+///
+async fn info(
+    options: web::Query<InfoOptions>,
+    configuration: web::Data<Configuration>,
+    client: web::Data<Client>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ImageError> {
+    if configuration.strict_query {
+        if let Err(err) = query::check_known(request.query_string(), &INFO_PARAMETERS) {
+            return Ok(error_response(StatusCode::BAD_REQUEST, &err));
+        }
+    }
+
+    let response = client.get(&options.source).await;
+
+    match response {
+        Ok(response) => {
+            let image = ResizableImage::from_bytes(
+                &response,
+                &configuration.source_limits,
+                &configuration.input_formats,
+                Deadline::after(configuration.resource_limits.time),
+            )?;
+
+            Ok(HttpResponse::Ok().json(image.info(response.len())?))
+        }
+        Err(err) => Ok(error_response(err.status_code(), &err)),
     }
 }
 
 impl error::ResponseError for ImageError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ImageError::InvalidColor | ImageError::InvalidCrop | ImageError::OutputTooLarge(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            ImageError::ImageTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ImageError::InvalidFormat | ImageError::UnsupportedFormat => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            ImageError::InvalidImage => StatusCode::UNPROCESSABLE_ENTITY,
+            ImageError::FailedWrite => StatusCode::INTERNAL_SERVER_ERROR,
+            ImageError::TimedOut => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::BadRequest().body(self.to_string())
+        error_response(self.status_code(), self)
+    }
+}
+
+impl ErrorCode for ImageError {
+    fn code(&self) -> &'static str {
+        match self {
+            ImageError::InvalidImage => "invalid_image",
+            ImageError::InvalidFormat => "invalid_format",
+            ImageError::UnsupportedFormat => "unsupported_format",
+            ImageError::FailedWrite => "failed_write",
+            ImageError::InvalidColor => "invalid_background",
+            ImageError::InvalidCrop => "invalid_crop",
+            ImageError::ImageTooLarge => "image_too_large",
+            ImageError::OutputTooLarge(_) => "output_too_large",
+            ImageError::TimedOut => "image_timed_out",
+        }
+    }
+
+    fn parameter(&self) -> Option<&str> {
+        match self {
+            ImageError::InvalidImage
+            | ImageError::UnsupportedFormat
+            | ImageError::ImageTooLarge => Some("source"),
+            ImageError::InvalidColor => Some("background"),
+            ImageError::InvalidCrop => Some("crop"),
+            ImageError::OutputTooLarge(Limit::Width) => Some("width"),
+            ImageError::OutputTooLarge(Limit::Height) => Some("height"),
+            ImageError::InvalidFormat
+            | ImageError::FailedWrite
+            | ImageError::OutputTooLarge(Limit::Area)
+            | ImageError::TimedOut => None,
+        }
     }
 }
 
@@ -191,6 +802,7 @@ pub fn run(
 ) -> Result<Server, std::io::Error> {
     START.call_once(|| {
         magick_wand_genesis();
+        configuration.resource_limits.apply();
     });
 
     let configuration = Data::new(configuration);
@@ -198,12 +810,19 @@ pub fn run(
     let statsd = Arc::new(statsd);
 
     let server = HttpServer::new(move || {
+        // every worker keeps its own long lived client so upstream connections are reused
+        let client = Data::new(Client::new(&configuration));
+
         App::new()
+            .wrap(ErrorFormat)
             .wrap(StatsD::new(statsd.clone()).exclude("/ping"))
             .wrap(Logger::default().exclude("/ping"))
             .route("/ping", web::get().to(ping))
             .route("/resize", web::get().to(resize))
+            .route("/info", web::get().to(info))
             .app_data(configuration.clone())
+            .app_data(client)
+            .app_data(web::QueryConfig::default().error_handler(query::error_handler))
     })
     .listen(listener)?
     .workers(workers)
@@ -1,48 +1,126 @@
 use cadence::{NopMetricSink, StatsdClient, UdpMetricSink, DEFAULT_PORT};
-use rusty_resizer::{run, Configuration};
+use rusty_resizer::{
+    run, Configuration, ConnectionPool, OutputLimits, ResourceLimits, Retries, SourceLimits,
+    Timeouts,
+};
 use std::env;
 use std::net::TcpListener;
 use std::net::UdpSocket;
+use std::str::FromStr;
 
 const DEFAULT_WORKERS: usize = 4;
 const DEFAULT_QUALITY: u8 = 85;
 const DEFAULT_CACHE_EXPIRATION_HOURS: u64 = 2880;
 const DEFAULT_CACHE_JITTER_SECONDS: u64 = 0;
 
+/// Parse an ENV var (`None` when it is not set or can not be parsed)
+fn parse_env<T: FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // ENV vars
     let port = env::var("PORT").unwrap_or_else(|_| String::from("8080"));
     let env = env::var("ENV").unwrap_or_else(|_| String::from("local"));
     let allowed_hosts = env::var("ALLOWED_HOSTS").expect("ALLOWED_HOSTS must be set!");
-    let workers = env::var("WORKERS")
-        .ok()
-        .and_then(|workers| workers.parse::<usize>().ok())
-        .unwrap_or(DEFAULT_WORKERS);
-    let default_quality = env::var("DEFAULT_QUALITY")
-        .ok()
-        .and_then(|dq| dq.parse::<u8>().ok())
-        .unwrap_or(DEFAULT_QUALITY);
-    let cache_expiration = env::var("CACHE_EXPIRATION_HOURS")
-        .ok()
-        .and_then(|ce| ce.parse::<u64>().ok())
-        .unwrap_or(DEFAULT_CACHE_EXPIRATION_HOURS);
-    let cache_jitter = env::var("CACHE_JITTER_SECONDS")
-        .ok()
-        .and_then(|ce| ce.parse::<u64>().ok())
-        .unwrap_or(DEFAULT_CACHE_JITTER_SECONDS);
+    let workers = parse_env("WORKERS").unwrap_or(DEFAULT_WORKERS);
+    let default_quality = parse_env("DEFAULT_QUALITY").unwrap_or(DEFAULT_QUALITY);
+    let cache_expiration =
+        parse_env("CACHE_EXPIRATION_HOURS").unwrap_or(DEFAULT_CACHE_EXPIRATION_HOURS);
+    let cache_jitter = parse_env("CACHE_JITTER_SECONDS").unwrap_or(DEFAULT_CACHE_JITTER_SECONDS);
+    let denied_hosts = env::var("DENIED_HOSTS").unwrap_or_default();
+    let blocked_addresses = env::var("BLOCKED_ADDRESSES").ok();
     let statsd_host = env::var("STATSD_HOST").ok();
     // App Configuration
     let address = format!("0.0.0.0:{}", port);
     let listener =
         TcpListener::bind(address).unwrap_or_else(|_| panic!("Failed to bind to port {}!", port));
-    let configuration = Configuration::new(
+    let mut configuration = Configuration::new(
         env.clone(),
         allowed_hosts,
         cache_expiration,
         cache_jitter,
         default_quality,
-    );
+    )
+    .and_then(|configuration| configuration.with_denied_hosts(denied_hosts))
+    .and_then(|configuration| match blocked_addresses {
+        Some(blocked_addresses) => configuration.with_blocked_addresses(blocked_addresses),
+        None => Ok(configuration),
+    })
+    .unwrap_or_else(|err| panic!("Invalid host configuration! {}", err))
+    .with_high_dpr_quality(parse_env("HIGH_DPR_QUALITY"))
+    .with_signing_keys(env::var("SIGNING_KEYS").unwrap_or_default());
+    // ENV vars that are not set keep the defaults of the configuration
+    if let Some(max_redirects) = parse_env("MAX_REDIRECTS") {
+        configuration = configuration.with_max_redirects(max_redirects);
+    }
+    if let Some(max_source_bytes) = parse_env("MAX_SOURCE_BYTES") {
+        configuration = configuration.with_max_source_bytes(max_source_bytes);
+    }
+    if let Some(enlarge) = parse_env("ENLARGE") {
+        configuration = configuration.with_enlarge(enlarge);
+    }
+    if let Some(max_dpr) = parse_env("MAX_DPR") {
+        configuration = configuration.with_max_dpr(max_dpr);
+    }
+    if let Some(client_hints) = parse_env("CLIENT_HINTS") {
+        configuration = configuration.with_client_hints(client_hints);
+    }
+    if let Some(save_data_quality) = parse_env("SAVE_DATA_QUALITY") {
+        configuration = configuration.with_save_data_quality(save_data_quality);
+    }
+    if let Some(strict_query) = parse_env("STRICT_QUERY") {
+        configuration = configuration.with_strict_query(strict_query);
+    }
+    let pool = configuration.connection_pool;
+    configuration = configuration.with_connection_pool(ConnectionPool {
+        size: parse_env("POOL_SIZE").unwrap_or(pool.size),
+        idle_timeout: parse_env("POOL_IDLE_TIMEOUT_SECONDS").unwrap_or(pool.idle_timeout),
+        per_origin: parse_env("MAX_CONNECTIONS_PER_ORIGIN").unwrap_or(pool.per_origin),
+    });
+    let timeouts = configuration.timeouts;
+    configuration = configuration.with_timeouts(Timeouts {
+        connect: parse_env("CONNECT_TIMEOUT_SECONDS").unwrap_or(timeouts.connect),
+        headers: parse_env("RESPONSE_TIMEOUT_SECONDS").unwrap_or(timeouts.headers),
+        body: parse_env("BODY_TIMEOUT_SECONDS").unwrap_or(timeouts.body),
+    });
+    let retries = configuration.retries;
+    configuration = configuration.with_retries(Retries {
+        attempts: parse_env("MAX_RETRIES").unwrap_or(retries.attempts),
+        backoff: parse_env("RETRY_BACKOFF_MILLISECONDS").unwrap_or(retries.backoff),
+    });
+    let source_limits = configuration.source_limits;
+    configuration = configuration.with_source_limits(SourceLimits {
+        pixels: parse_env("MAX_SOURCE_PIXELS").unwrap_or(source_limits.pixels),
+        width: parse_env("MAX_SOURCE_WIDTH").unwrap_or(source_limits.width),
+        height: parse_env("MAX_SOURCE_HEIGHT").unwrap_or(source_limits.height),
+        frames: parse_env("MAX_SOURCE_FRAMES").unwrap_or(source_limits.frames),
+    });
+    let output_limits = configuration.output_limits;
+    configuration = configuration.with_output_limits(OutputLimits {
+        width: parse_env("MAX_OUTPUT_WIDTH").unwrap_or(output_limits.width),
+        height: parse_env("MAX_OUTPUT_HEIGHT").unwrap_or(output_limits.height),
+        area: parse_env("MAX_OUTPUT_AREA").unwrap_or(output_limits.area),
+        clamp: parse_env("CLAMP_OUTPUT").unwrap_or(output_limits.clamp),
+    });
+    let resource_limits = configuration.resource_limits;
+    configuration = configuration.with_resource_limits(ResourceLimits {
+        memory: parse_env("MAX_MEMORY_BYTES").unwrap_or(resource_limits.memory),
+        map: parse_env("MAX_MAP_BYTES").unwrap_or(resource_limits.map),
+        disk: parse_env("MAX_DISK_BYTES").unwrap_or(resource_limits.disk),
+        time: parse_env("MAX_DECODE_SECONDS").unwrap_or(resource_limits.time),
+    });
+    if let Ok(auto_formats) = env::var("AUTO_FORMATS") {
+        configuration = configuration
+            .with_auto_formats(auto_formats)
+            .unwrap_or_else(|err| panic!("Invalid format configuration! {}", err));
+    }
+    if let Ok(input_formats) = env::var("INPUT_FORMATS") {
+        configuration = configuration
+            .with_input_formats(input_formats)
+            .unwrap_or_else(|err| panic!("Invalid format configuration! {}", err));
+    }
     // Logging
     use env_logger;
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
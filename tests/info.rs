@@ -0,0 +1,63 @@
+mod support;
+use support::spawn_app;
+
+#[actix_rt::test]
+async fn test_info_returns_image_details_as_json() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image one is a 2250px x 2250px JPEG image with a file size of 1723518 bytes
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!("{}/info?source={}", address, test_image_one))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/json",
+        "content type is equal to application/json"
+    );
+
+    let text = response.text().await.expect("Failed to read response text");
+    let info: serde_json::Value = serde_json::from_str(&text).expect("Failed to parse JSON");
+
+    assert_eq!(info["width"], 2250, "width is equal to 2250px");
+    assert_eq!(info["height"], 2250, "height is equal to 2250px");
+    assert_eq!(info["format"], "jpeg", "format is equal to jpeg");
+    assert_eq!(
+        info["mime_type"], "image/jpeg",
+        "mime type is equal to image/jpeg"
+    );
+    assert_eq!(info["frames"], 1, "image has a single frame");
+    assert_eq!(info["has_alpha"], false, "image has no alpha channel");
+    assert_eq!(info["bytes"], 1723518, "byte size is equal to 1723518");
+}
+
+#[actix_rt::test]
+async fn test_info_returns_error_if_image_host_is_not_allowed() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/info?source=https://content.com/test.jpg",
+            address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Image Host Is Not Allowed", text);
+}
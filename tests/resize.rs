@@ -1,8 +1,21 @@
 mod support;
 use std::io::Cursor;
-
-use image::{guess_format, io::Reader as ImageReader, GenericImageView, ImageFormat};
-use support::spawn_app;
+use std::net::TcpListener;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use image::{
+    guess_format, io::Reader as ImageReader, GenericImageView, ImageFormat, ImageOutputFormat, Rgb,
+    RgbImage,
+};
+use rusty_resizer::{
+    Configuration, ConnectionPool, OutputLimits, ResourceLimits, Retries, SourceLimits, Timeouts,
+};
+use support::{
+    spawn_app, spawn_app_with, spawn_image_host, spawn_local_app, spawn_slow_image_host,
+};
 
 #[actix_rt::test]
 async fn test_resize_requires_source_query_params() {
@@ -65,12 +78,256 @@ async fn test_resize_returns_error_if_image_host_is_not_allowed() {
         .expect("Failed to execute request.");
 
     // Assert
-    assert!(response.status().is_client_error());
+    assert_eq!(403, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Image Host Is Not Allowed", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_returns_not_found_if_the_image_does_not_exist() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    let missing_image =
+        "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/missing.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, missing_image
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Image Not Found", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_returns_json_errors_when_requested() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    let test_image_one = "https://content.com/test.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, test_image_one
+        ))
+        .header("Accept", "application/json")
+        .header("X-Request-Id", "request-123")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+    assert_eq!("request-123", response.headers()["X-Request-Id"]);
+
+    let text = response.text().await.expect("Failed to read response text");
+    let error: serde_json::Value = serde_json::from_str(&text).expect("Failed to parse JSON");
+    assert_eq!(error["code"], "host_not_allowed");
+    assert_eq!(error["message"], "Image Host Is Not Allowed");
+    assert_eq!(error["parameter"], "source");
+    assert_eq!(error["request_id"], "request-123");
+}
+
+#[actix_rt::test]
+async fn test_resize_names_parameters_that_can_not_be_parsed() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    for (query, parameter) in [
+        ("width=abc", "width"),
+        ("format=svg", "format"),
+        ("mode=squash", "mode"),
+        ("gravity=up", "gravity"),
+        ("crop=1,2", "crop"),
+        ("enlarge=maybe", "enlarge"),
+    ] {
+        // Act
+        let response = client
+            .get(format!(
+                "{}/resize?source={}&{}",
+                address, test_image_one, query
+            ))
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // Assert
+        assert_eq!(400, response.status().as_u16());
+
+        let text = response.text().await.expect("Failed to read response text");
+        let error: serde_json::Value = serde_json::from_str(&text).expect("Failed to parse JSON");
+        assert_eq!(error["code"], "invalid_query");
+        assert_eq!(error["parameter"], parameter);
+    }
+}
+
+#[actix_rt::test]
+async fn test_resize_returns_error_if_image_host_is_denied() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        configuration
+            .with_denied_hosts(String::from("*.githubusercontent.com"))
+            .expect("Failed to parse denied hosts")
+    });
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Image Host Is Not Allowed", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_returns_error_if_image_path_is_denied() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        configuration
+            .with_denied_hosts(String::from(
+                "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/",
+            ))
+            .expect("Failed to parse denied hosts")
+    });
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Image Host Is Not Allowed", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_follows_redirects_to_allowed_hosts() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        Configuration::new(
+            configuration.env,
+            String::from("github.com, raw.githubusercontent.com"),
+            1,
+            0,
+            85,
+        )
+        .expect("Failed to parse allowed hosts")
+    });
+    let client = reqwest::Client::new();
+    let test_image_one =
+        "https://github.com/walterbm/rusty-resizer/raw/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_resize_returns_error_if_image_redirects_to_a_host_that_is_not_allowed() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        Configuration::new(configuration.env, String::from("github.com"), 1, 0, 85)
+            .expect("Failed to parse allowed hosts")
+    });
+    let client = reqwest::Client::new();
+    let test_image_one =
+        "https://github.com/walterbm/rusty-resizer/raw/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
 
     let text = response.text().await.expect("Failed to read response text");
     assert_eq!("Image Host Is Not Allowed", text);
 }
 
+#[actix_rt::test]
+async fn test_resize_returns_error_if_image_redirects_too_many_times() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        Configuration::new(
+            configuration.env,
+            String::from("github.com, raw.githubusercontent.com"),
+            1,
+            0,
+            85,
+        )
+        .expect("Failed to parse allowed hosts")
+        .with_max_redirects(0)
+    });
+    let client = reqwest::Client::new();
+    let test_image_one =
+        "https://github.com/walterbm/rusty-resizer/raw/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(502, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Too Many Redirects For Image", text);
+}
+
 #[actix_rt::test]
 async fn test_resize_can_resize_an_image() {
     // Arrange
@@ -538,3 +795,1145 @@ async fn test_resize_can_convert_source_image_based_on_automatic_content_negotia
     assert_eq!(width, 225, "width is equal to 225px");
     assert_eq!(height, 225, "height is equal to 225px");
 }
+
+#[actix_rt::test]
+async fn test_resize_can_fill_exact_dimensions_by_cropping() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image two has a 4:3 aspect ratio
+    let test_image_two = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-two.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=300&height=300&mode=fill",
+            address, test_image_two
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 300, "width is equal to 300px");
+    assert_eq!(height, 300, "height is equal to 300px");
+}
+
+#[actix_rt::test]
+async fn test_resize_can_pad_exact_dimensions_with_a_background() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image two has a 4:3 aspect ratio
+    let test_image_two = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-two.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=300&height=300&mode=pad&background=black",
+            address, test_image_two
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 300, "width is equal to 300px");
+    assert_eq!(height, 300, "height is equal to 300px");
+
+    let corner = image.to_rgb8().get_pixel(0, 0).0;
+    assert!(
+        corner.iter().all(|channel| *channel < 16),
+        "letterbox is filled with the black background"
+    );
+}
+
+#[actix_rt::test]
+async fn test_resize_can_stretch_an_image_ignoring_aspect_ratio() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image two has a 4:3 aspect ratio
+    let test_image_two = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-two.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=300&height=100&mode=stretch",
+            address, test_image_two
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 300, "width is equal to 300px");
+    assert_eq!(height, 100, "height is equal to 100px");
+}
+
+#[actix_rt::test]
+async fn test_resize_can_fill_exact_dimensions_around_a_focal_point() {
+    // Arrange
+    let address = spawn_local_app();
+    let client = reqwest::Client::new();
+    // 400x300 black image with a white stripe along its left edge
+    let image_host = spawn_image_host(png(RgbImage::from_fn(400, 300, |x, _| {
+        if x < 50 {
+            Rgb([255, 255, 255])
+        } else {
+            Rgb([0, 0, 0])
+        }
+    })));
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}/image.png&width=300&height=300&mode=fill&fx=0.1&fy=0.9",
+            address, image_host
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image")
+        .to_luma8();
+    assert_eq!(image.dimensions(), (300, 300), "image is 300x300px");
+    // a centered crop would start 50px in and cut the stripe off entirely
+    assert!(
+        image.get_pixel(10, 150)[0] > 200,
+        "crop keeps the left edge"
+    );
+    assert!(image.get_pixel(100, 150)[0] < 50, "crop is not shifted");
+}
+
+#[actix_rt::test]
+async fn test_resize_can_fill_exact_dimensions_with_a_smart_crop() {
+    // Arrange
+    let address = spawn_local_app();
+    let client = reqwest::Client::new();
+    // 400x300 flat gray image with a detailed checkerboard along its right edge
+    let image_host = spawn_image_host(png(RgbImage::from_fn(400, 300, |x, y| {
+        if x < 300 {
+            Rgb([128, 128, 128])
+        } else if (x / 16 + y / 16) % 2 == 0 {
+            Rgb([255, 255, 255])
+        } else {
+            Rgb([0, 0, 0])
+        }
+    })));
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}/image.png&width=300&height=300&mode=fill&gravity=smart",
+            address, image_host
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image")
+        .to_luma8();
+    assert_eq!(image.dimensions(), (300, 300), "image is 300x300px");
+    // only a crop pushed against the right edge shows the checkerboard from 200px onwards
+    let stripe = (200..250).flat_map(|x| (0..300).map(move |y| (x, y)));
+    let luma = stripe
+        .map(|(x, y)| image.get_pixel(x, y)[0])
+        .collect::<Vec<_>>();
+    assert!(
+        luma.iter().any(|luma| *luma < 50),
+        "crop shows dark squares"
+    );
+    assert!(
+        luma.iter().any(|luma| *luma > 200),
+        "crop shows light squares"
+    );
+}
+
+fn png(image: RgbImage) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    image
+        .write_to(&mut bytes, ImageOutputFormat::Png)
+        .expect("Failed to encode image");
+    bytes.into_inner()
+}
+
+#[actix_rt::test]
+async fn test_resize_can_crop_the_source_before_resizing() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image one has dimensions of 2250px x 2250px
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&crop=0,0,1000,500&width=500",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 500, "width is equal to 500px");
+    assert_eq!(height, 250, "height is equal to 250px");
+}
+
+#[actix_rt::test]
+async fn test_resize_returns_error_if_crop_is_outside_of_the_source() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image one has dimensions of 2250px x 2250px
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&crop=2000,0,1000,500",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_client_error());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Crop Is Outside Of The Image Bounds", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_does_not_enlarge_an_image_when_enlarge_is_disabled() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image two has dimensions of 2048px x 1536px
+    let test_image_two = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-two.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=4000&enlarge=false",
+            address, test_image_two
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 2048, "width is equal to the original 2048px");
+    assert_eq!(height, 1536, "height is equal to the original 1536px");
+}
+
+#[actix_rt::test]
+async fn test_resize_fits_within_both_dimensions_when_enlarge_is_disabled() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image two has dimensions of 2048px x 1536px
+    let test_image_two = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-two.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=4000&height=1000&enlarge=false",
+            address, test_image_two
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 1333, "width is scaled to fit the 1000px height");
+    assert_eq!(height, 1000, "height is equal to 1000px");
+}
+
+#[actix_rt::test]
+async fn test_resize_pads_without_enlarging_when_enlarge_is_disabled() {
+    // Arrange
+    let address = spawn_local_app();
+    let client = reqwest::Client::new();
+    // 200x100 white image
+    let image_host = spawn_image_host(png(RgbImage::from_pixel(200, 100, Rgb([255, 255, 255]))));
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}/image.png&width=1000&height=1000&mode=pad&background=black&enlarge=false",
+            address, image_host
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image")
+        .to_rgb8();
+    assert_eq!(image.dimensions(), (1000, 1000), "box is kept as requested");
+    assert!(
+        image
+            .get_pixel(500, 500)
+            .0
+            .iter()
+            .all(|channel| *channel > 240),
+        "image is centered in the box"
+    );
+    assert!(
+        image
+            .get_pixel(500, 440)
+            .0
+            .iter()
+            .all(|channel| *channel < 16)
+            && image
+                .get_pixel(390, 500)
+                .0
+                .iter()
+                .all(|channel| *channel < 16),
+        "image keeps its original 200px x 100px size"
+    );
+}
+
+#[actix_rt::test]
+async fn test_resize_can_multiply_dimensions_by_a_capped_device_pixel_ratio() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let fractional = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100&dpr=1.5",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let capped = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100&dpr=10",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    for (response, expected) in [(fractional, 150), (capped, 300)] {
+        assert!(response.status().is_success());
+
+        let bytes = response
+            .bytes()
+            .await
+            .expect("Failed to read response bytes");
+
+        let image = ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .unwrap()
+            .decode()
+            .expect("Failed to decode image");
+        let (width, height) = image.dimensions();
+        assert_eq!(width, expected, "width is equal to {}px", expected);
+        assert_eq!(height, expected, "height is equal to {}px", expected);
+    }
+}
+
+#[actix_rt::test]
+async fn test_resize_can_size_an_image_with_client_hints() {
+    // Arrange
+    let address = spawn_app_with(|configuration| configuration.with_client_hints(true));
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!("{}/resize?source={}", address, test_image_one))
+        .header("Sec-CH-Width", "300")
+        .header("Sec-CH-DPR", "2")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+    assert_eq!(
+        response.headers().get("Accept-CH").unwrap(),
+        "Sec-CH-DPR, Sec-CH-Width, Sec-CH-Viewport-Width",
+        "response asks for client hints"
+    );
+    assert_eq!(
+        response.headers().get("Vary").unwrap(),
+        "Sec-CH-DPR, Sec-CH-Width, Sec-CH-Viewport-Width, Save-Data",
+        "response varies by the client hints"
+    );
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 300, "width is equal to the physical 300px hint");
+    assert_eq!(height, 300, "height is equal to 300px");
+}
+
+#[actix_rt::test]
+async fn test_resize_automatic_content_negotiation_respects_quality_values() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        configuration
+            .with_auto_formats(String::from("avif,webp"))
+            .expect("Failed to parse auto formats")
+    });
+    let client = reqwest::Client::new();
+    // test image one is a JPEG image
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=225&height=225&format=auto",
+            address, test_image_one
+        ))
+        .header("Accept", "image/avif;q=0.5,image/webp,image/*;q=0.8")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "image/webp",
+        "content type is equal to image/webp"
+    );
+    assert_eq!(
+        response.headers().get("Vary").unwrap(),
+        "Accept",
+        "response varies by request 'accept' header"
+    );
+}
+
+#[actix_rt::test]
+async fn test_resize_automatic_content_negotiation_keeps_the_original_format_without_support() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image one is a JPEG image
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=225&height=225&format=auto",
+            address, test_image_one
+        ))
+        .header("Accept", "image/png,image/*;q=0.8")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "image/jpeg",
+        "content type is equal to image/jpeg"
+    );
+    assert_eq!(
+        response.headers().get("Vary").unwrap(),
+        "Accept",
+        "response varies by request 'accept' header"
+    );
+}
+
+/// HMAC-SHA256 signature of a query string that is already in canonical order
+fn sign(canonical: &str, key: &str) -> String {
+    let key = openssl::pkey::PKey::hmac(key.as_bytes()).unwrap();
+    let mut signer =
+        openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key).unwrap();
+    signer
+        .sign_oneshot_to_vec(canonical.as_bytes())
+        .unwrap()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[actix_rt::test]
+async fn test_resize_ignores_client_hints_when_signing_keys_are_configured() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        configuration
+            .with_client_hints(true)
+            .with_signing_keys(String::from("current"))
+    });
+    let client = reqwest::Client::new();
+    let query = "height=100&source=https%3A%2F%2Fraw.githubusercontent.com%2Fwalterbm%2Frusty-resizer%2Fmain%2Ftests%2Ffixtures%2Ftest-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?{}&signature={}",
+            address,
+            query,
+            sign(query, "current")
+        ))
+        .header("Sec-CH-Width", "300")
+        .header("Sec-CH-DPR", "2")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+    assert!(
+        response.headers().get("Accept-CH").is_none(),
+        "response does not ask for client hints"
+    );
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 100, "width is only set by the signed height");
+    assert_eq!(height, 100, "height is equal to the signed 100px");
+}
+
+#[actix_rt::test]
+async fn test_resize_requires_a_valid_signature_when_signing_keys_are_configured() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        configuration.with_signing_keys(String::from("current,previous"))
+    });
+    let client = reqwest::Client::new();
+    let query = "height=100&source=https%3A%2F%2Fraw.githubusercontent.com%2Fwalterbm%2Frusty-resizer%2Fmain%2Ftests%2Ffixtures%2Ftest-image-one.jpg&width=100";
+
+    // Act
+    let unsigned = client
+        .get(format!("{}/resize?{}", address, query))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let tampered = client
+        .get(format!(
+            "{}/resize?{}&signature={}",
+            address,
+            query.replace("width=100", "width=9999"),
+            sign(query, "current")
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let signed = client
+        .get(format!(
+            "{}/resize?{}&signature={}",
+            address,
+            query,
+            sign(query, "previous")
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(unsigned.status(), 403, "unsigned request is forbidden");
+    assert_eq!(
+        unsigned.text().await.expect("Failed to read response text"),
+        "Missing Signature"
+    );
+    assert_eq!(tampered.status(), 403, "tampered request is forbidden");
+    assert_eq!(
+        tampered.text().await.expect("Failed to read response text"),
+        "Invalid Signature"
+    );
+    assert!(
+        signed.status().is_success(),
+        "request signed with a rotated key is allowed"
+    );
+}
+
+#[actix_rt::test]
+async fn test_resize_returns_gone_for_expired_urls() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&expires=1000",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status(), 410, "expired url is gone");
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Expired URL", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_clamps_cache_headers_to_the_lifetime_of_expiring_urls() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+    let expires = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        + 600;
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&expires={}",
+            address, test_image_one, expires
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let max_age = response
+        .headers()
+        .get("Cache-Control")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .trim_start_matches("max-age=")
+        .parse::<u64>()
+        .unwrap();
+    assert!(
+        max_age <= 600,
+        "cache control max age does not outlive the url"
+    );
+}
+
+#[actix_rt::test]
+async fn test_resize_rejects_images_exceeding_the_source_limits() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        let source_limits = SourceLimits {
+            pixels: 1_000_000,
+            ..configuration.source_limits
+        };
+        configuration.with_source_limits(source_limits)
+    });
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(413, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Image Is Too Large", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_returns_service_unavailable_if_the_image_takes_too_long_to_process() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        let resource_limits = ResourceLimits {
+            time: 0,
+            ..configuration.resource_limits
+        };
+        configuration.with_resource_limits(resource_limits)
+    });
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(503, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Timed Out Processing Image", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_rejects_dimensions_exceeding_the_output_limits() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    let test_image_two = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-two.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100000",
+            address, test_image_two
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Width Exceeds The Maximum Output Width", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_checks_the_output_limits_after_the_enlarge_cap() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    // test image two has dimensions of 2048px x 1536px
+    let test_image_two = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-two.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=9000&enlarge=false",
+            address, test_image_two
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 2048, "width is capped to the original 2048px");
+    assert_eq!(height, 1536, "height is capped to the original 1536px");
+}
+
+#[actix_rt::test]
+async fn test_resize_clamps_dimensions_exceeding_the_output_limits_when_enabled() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        let output_limits = OutputLimits {
+            width: 500,
+            clamp: true,
+            ..configuration.output_limits
+        };
+        configuration.with_output_limits(output_limits)
+    });
+    let client = reqwest::Client::new();
+    // test image two has dimensions of 2048px x 1536px
+    let test_image_two = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-two.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=1000",
+            address, test_image_two
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+
+    let bytes = response
+        .bytes()
+        .await
+        .expect("Failed to read response bytes");
+
+    let image = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .unwrap()
+        .decode()
+        .expect("Failed to decode image");
+    let (width, height) = image.dimensions();
+    assert_eq!(width, 500, "width is clamped to 500px");
+    assert_eq!(height, 375, "height preserves the aspect ratio");
+}
+
+#[actix_rt::test]
+async fn test_resize_rejects_images_in_formats_that_are_not_allowed() {
+    // Arrange
+    let address = spawn_app_with(|configuration| {
+        configuration
+            .with_input_formats(String::from("png"))
+            .expect("Failed to parse input formats")
+    });
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(415, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Image Format Is Not Allowed", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_rejects_images_exceeding_the_source_byte_limit() {
+    // Arrange
+    let address = spawn_app_with(|configuration| configuration.with_max_source_bytes(1_000));
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&height=100",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(413, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Image Payload Is Too Large", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_caps_concurrent_requests_to_an_image_host() {
+    // Arrange
+    let address = spawn_app_with(|_| {
+        let configuration =
+            Configuration::new(String::from("test"), String::from("127.0.0.1"), 1, 0, 85)
+                .and_then(|configuration| configuration.with_blocked_addresses(String::new()))
+                .expect("Failed to parse allowed hosts");
+        let connection_pool = ConnectionPool {
+            per_origin: 2,
+            ..configuration.connection_pool
+        };
+        configuration.with_connection_pool(connection_pool)
+    });
+    let client = reqwest::Client::new();
+    let (image_host, concurrency) =
+        spawn_slow_image_host(png(RgbImage::new(10, 10)), Duration::from_millis(200));
+
+    // Act
+    let responses = join_all((0..6).map(|_| {
+        client
+            .get(format!(
+                "{}/resize?source={}/image.png",
+                address, image_host
+            ))
+            .send()
+    }))
+    .await;
+
+    // Assert
+    for response in responses {
+        assert!(response
+            .expect("Failed to execute request.")
+            .status()
+            .is_success());
+    }
+    assert_eq!(
+        2,
+        concurrency.load(Ordering::SeqCst),
+        "image host never serves more than 2 requests at a time"
+    );
+}
+
+#[actix_rt::test]
+async fn test_resize_returns_gateway_timeout_if_the_image_host_does_not_respond() {
+    // Arrange
+    let address = spawn_app_with(|_| {
+        Configuration::new(String::from("test"), String::from("127.0.0.1"), 1, 0, 85)
+            .and_then(|configuration| configuration.with_blocked_addresses(String::new()))
+            .expect("Failed to parse allowed hosts")
+            .with_timeouts(Timeouts {
+                connect: 1,
+                headers: 1,
+                body: 1,
+            })
+            .with_retries(Retries {
+                attempts: 0,
+                backoff: 0,
+            })
+    });
+    let client = reqwest::Client::new();
+    // accept connections to the image host but never respond
+    let upstream = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random system port");
+    let upstream_port = upstream.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let _connections = upstream.incoming().collect::<Vec<_>>();
+    });
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source=http://127.0.0.1:{}/image.jpg&width=100",
+            address, upstream_port
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(504, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Timed Out Fetching Image", text);
+}
+
+#[actix_rt::test]
+async fn test_resize_rejects_out_of_range_parameters() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    for (query, message) in [
+        (
+            "width=-100",
+            "Parameter `width` Must Be A Number Between 1 And 100000",
+        ),
+        (
+            "height=NaN",
+            "Parameter `height` Must Be A Number Between 1 And 100000",
+        ),
+        (
+            "width=100&quality=101",
+            "Parameter `quality` Must Be A Number Between 1 And 100",
+        ),
+        (
+            "width=100&quality=300",
+            "Parameter `quality` Must Be A Number Between 1 And 100",
+        ),
+        (
+            "width=100&fx=1.5",
+            "Parameter `fx` Must Be A Number Between 0 And 1",
+        ),
+    ] {
+        // Act
+        let response = client
+            .get(format!(
+                "{}/resize?source={}&{}",
+                address, test_image_one, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+
+        // Assert
+        assert_eq!(400, response.status().as_u16());
+
+        let text = response.text().await.expect("Failed to read response text");
+        assert_eq!(message, text);
+    }
+}
+
+#[actix_rt::test]
+async fn test_resize_ignores_unknown_parameters_by_default() {
+    // Arrange
+    let address = spawn_app();
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&max_quality=85",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert!(response.status().is_success());
+}
+
+#[actix_rt::test]
+async fn test_resize_rejects_unknown_parameters_in_strict_mode() {
+    // Arrange
+    let address = spawn_app_with(|configuration| configuration.with_strict_query(true));
+    let client = reqwest::Client::new();
+    let test_image_one = "https://raw.githubusercontent.com/walterbm/rusty-resizer/main/tests/fixtures/test-image-one.jpg";
+
+    // Act
+    let response = client
+        .get(format!(
+            "{}/resize?source={}&width=100&max_quality=85",
+            address, test_image_one
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+
+    let text = response.text().await.expect("Failed to read response text");
+    assert_eq!("Unknown Parameter `max_quality`", text);
+}
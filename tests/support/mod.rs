@@ -1,19 +1,38 @@
-use std::{collections::HashSet, net::TcpListener};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use cadence::{NopMetricSink, StatsdClient};
 use rusty_resizer::Configuration;
-use url::Host;
 
 pub fn spawn_app() -> String {
+    spawn_app_with(|configuration| configuration)
+}
+
+pub fn spawn_app_with(configure: fn(Configuration) -> Configuration) -> String {
+    spawn("raw.githubusercontent.com", configure)
+}
+
+/// Spawn an app that only fetches images from local image hosts (see `spawn_image_host`)
+#[allow(dead_code)]
+pub fn spawn_local_app() -> String {
+    spawn("127.0.0.1", |configuration| {
+        configuration
+            .with_blocked_addresses(String::new())
+            .expect("Failed to parse blocked addresses")
+    })
+}
+
+fn spawn(allowed_host: &str, configure: fn(Configuration) -> Configuration) -> String {
     let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random system port");
     let port = listener.local_addr().unwrap().port();
-    let configuration = Configuration {
-        env: String::from("test"),
-        allowed_hosts: HashSet::from_iter(vec![Host::parse("raw.githubusercontent.com").unwrap()]),
-        cache_expiration: 1,
-        cache_jitter: 0,
-        default_quality: 85,
-    };
+    let configuration = configure(
+        Configuration::new(String::from("test"), String::from(allowed_host), 1, 0, 85)
+            .expect("Failed to parse allowed hosts"),
+    );
     let statsd = StatsdClient::from_sink("rusty.resizer", NopMetricSink);
     let workers = 1;
     let server = rusty_resizer::run(listener, configuration, statsd, workers)
@@ -23,3 +42,54 @@ pub fn spawn_app() -> String {
 
     format!("http://127.0.0.1:{}", port)
 }
+
+/// Serve `image` to every request from a local image host and return the address of that host
+#[allow(dead_code)]
+pub fn spawn_image_host(image: Vec<u8>) -> String {
+    let (address, _) = spawn_slow_image_host(image, Duration::ZERO);
+    address
+}
+
+/// Serve `image` to every request after a `delay` and return the address of the image host
+/// along with the highest number of requests it handled at the same time
+#[allow(dead_code)]
+pub fn spawn_slow_image_host(image: Vec<u8>, delay: Duration) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind to random system port");
+    let port = listener.local_addr().unwrap().port();
+    let image = Arc::new(image);
+    let (active, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let concurrency = Arc::clone(&peak);
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let (image, active, peak) =
+                (Arc::clone(&image), Arc::clone(&active), Arc::clone(&peak));
+
+            thread::spawn(move || {
+                // read until the end of the request headers (image requests have no body)
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => return,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+
+                peak.fetch_max(active.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                thread::sleep(delay);
+
+                let headers = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    image.len()
+                );
+                let _ = stream
+                    .write_all(headers.as_bytes())
+                    .and_then(|_| stream.write_all(&image));
+                active.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+
+    (format!("http://127.0.0.1:{}", port), concurrency)
+}